@@ -1,8 +1,6 @@
-#[allow(dead_code)]
-
-use std::collections::HashMap;
 use std::cmp::PartialEq;
-use core_stable::token;
+use std::fmt;
+use core_stable::span::Span;
 
 pub type Identifier = String;
 pub type BlockStatement = Vec<Statement>;
@@ -17,11 +15,13 @@ pub enum PrefixOperator {
     Personnalised(String),
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum InfixOperator {
     Plus,
     Minus,
+    Mul,
+    Div,
     Eq,
     NotEq,
     GT,
@@ -46,26 +46,70 @@ pub enum Operator {
     Suffix(SuffixOperator),
 }
 
+/// A node of the tree, with the span of the source it was parsed from.
+///
+/// Spans are ignored when comparing nodes: two trees are equal when they
+/// describe the same program, however it is laid out.
+#[derive(Debug, Clone)]
+pub struct Node<Kind> {
+    pub kind: Kind,
+    pub span: Span,
+}
+
+impl<Kind> Node<Kind> {
+    pub fn new(kind: Kind, span: Span) -> Self {
+        Self {
+            kind,
+            span
+        }
+    }
+}
+
+impl<Kind: PartialEq> PartialEq for Node<Kind> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+pub type Statement = Node<StatementKind>;
+pub type Expression = Node<ExpressionKind>;
+
 #[allow(dead_code)]
 #[derive(PartialEq, Debug, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     ///Expression
     Expr(Expression),
 
-    /// Keywords
-    Let{ name: Identifier, value: Expression },
+    /// Keywords. `doc` is the text of the `///` comments before the `let`,
+    /// one line per comment.
+    Let{ name: Identifier, value: Expression, doc: Option<String> },
     Return(Expression),
 }
 
 impl From<Expression> for Statement {
     fn from(expr: Expression) -> Statement {
-        Statement::Expr(expr)
+        let span = expr.span;
+        Node::new(StatementKind::Expr(expr), span)
     }
 }
 
-#[allow(dead_code)]
+/// A node without location, for the nodes built by hand.
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Statement {
+        Node::new(kind, Span::default())
+    }
+}
+
+/// A node without location, for the nodes built by hand.
+impl From<ExpressionKind> for Expression {
+    fn from(kind: ExpressionKind) -> Expression {
+        Node::new(kind, Span::default())
+    }
+}
+
+#[allow(dead_code, clippy::enum_variant_names)]
 #[derive(PartialEq, Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     /// Identifiers + literals
     Ident(Identifier),
     Int(isize),
@@ -73,6 +117,7 @@ pub enum Expression {
     String(String),
     Boolean(bool),
     Function{ params: Parameters, body: Box<Expression> },
+    MacroLiteral{ params: Parameters, body: Box<Expression> },
 
     /// Expressions
     PrefixExpression{ operator: Operator, right: Box<Expression> },
@@ -80,11 +125,15 @@ pub enum Expression {
     PostfixExpression{ right: Box<Expression>, op: Operator },
     IfExpression{ condition: Box<Expression>, consequence: Box<Statement>, alternative: Box<Statement> },
     CallExpression{ lambda: Box<Expression>, parameters: Vec<Expression> },
+    ArrayLiteral(Vec<Expression>),
+    HashLiteral(Vec<(Expression, Expression)>),
+    IndexExpression{ left: Box<Expression>, index: Box<Expression> },
     BlockExpression(BlockStatement),
     Unit
 }
 
 #[allow(dead_code)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Program {
     pub global: BlockStatement,
 }
@@ -92,7 +141,206 @@ pub struct Program {
 impl From<BlockStatement> for Program {
     fn from(global: BlockStatement) -> Program {
         Program {
-            global: global
+            global
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Prefix(PrefixOperator::Plus) | Operator::Infix(InfixOperator::Plus) => write!(f, "+"),
+            Operator::Prefix(PrefixOperator::Minus) | Operator::Infix(InfixOperator::Minus) => write!(f, "-"),
+            Operator::Prefix(PrefixOperator::Bang) | Operator::Suffix(SuffixOperator::Bang) => write!(f, "!"),
+            Operator::Infix(InfixOperator::Mul) => write!(f, "*"),
+            Operator::Infix(InfixOperator::Div) => write!(f, "/"),
+            Operator::Infix(InfixOperator::Eq) => write!(f, "=="),
+            Operator::Infix(InfixOperator::NotEq) => write!(f, "!="),
+            Operator::Infix(InfixOperator::GT) => write!(f, ">"),
+            Operator::Infix(InfixOperator::LT) => write!(f, "<"),
+            Operator::Infix(InfixOperator::GTE) => write!(f, ">="),
+            Operator::Infix(InfixOperator::LTE) => write!(f, "<="),
+            Operator::Prefix(PrefixOperator::Personnalised(op))
+            | Operator::Infix(InfixOperator::Personalised(op))
+            | Operator::Suffix(SuffixOperator::Personnalised(op)) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    items.iter().map(T::to_string).collect::<Vec<String>>().join(separator)
+}
+
+/// Writes the statements back as source, one after the other.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", join(&self.global, "; "))
+    }
+}
+
+impl fmt::Display for Node<StatementKind> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            StatementKind::Expr(expression) => write!(f, "{}", expression),
+            StatementKind::Let{ name, value, .. } => write!(f, "let {} = {}", name, value),
+            StatementKind::Return(expression) => write!(f, "return {}", expression),
+        }
+    }
+}
+
+/// Writes the expression back as source, with every operation parenthesized
+/// so that the precedence shows: `-a * b` is written `((-a) * b)`.
+impl fmt::Display for Node<ExpressionKind> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExpressionKind::Ident(name) => write!(f, "{}", name),
+            ExpressionKind::Int(value) => write!(f, "{}", value),
+            ExpressionKind::Float(value) => write!(f, "{:?}", value),
+            ExpressionKind::String(value) => write!(f, "{:?}", value),
+            ExpressionKind::Boolean(value) => write!(f, "{}", value),
+            ExpressionKind::Function{ params, body } => write!(f, "fn({}) {}", params.join(", "), body),
+            ExpressionKind::MacroLiteral{ params, body } => write!(f, "macro({}) {}", params.join(", "), body),
+            ExpressionKind::PrefixExpression{ operator, right } => write!(f, "({}{})", operator, right),
+            ExpressionKind::InfixExpression{ left, op, right } => write!(f, "({} {} {})", left, op, right),
+            ExpressionKind::PostfixExpression{ right, op } => write!(f, "({}{})", right, op),
+            ExpressionKind::IfExpression{ condition, consequence, alternative } => {
+                write!(f, "if ({}) {}", condition, consequence)?;
+                match &alternative.kind {
+                    StatementKind::Expr(Node { kind: ExpressionKind::Unit, .. }) => Ok(()),
+                    _ => write!(f, " else {}", alternative),
+                }
+            },
+            ExpressionKind::CallExpression{ lambda, parameters } => write!(f, "{}({})", lambda, join(parameters, ", ")),
+            ExpressionKind::ArrayLiteral(elements) => write!(f, "[{}]", join(elements, ", ")),
+            ExpressionKind::HashLiteral(pairs) => {
+                let pairs: Vec<String> = pairs.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            },
+            ExpressionKind::IndexExpression{ left, index } => write!(f, "({}[{}])", left, index),
+            ExpressionKind::BlockExpression(statements) if statements.is_empty() => write!(f, "{{}}"),
+            ExpressionKind::BlockExpression(statements) => write!(f, "{{ {} }}", join(statements, "; ")),
+            ExpressionKind::Unit => write!(f, "()"),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Called by `modify` on every expression of a tree.
+pub type Modifier<'a> = dyn FnMut(Expression) -> Expression + 'a;
+
+impl Program {
+    /// Rebuilds the program, passing each of its expressions through
+    /// `modifier` (see `Node::<ExpressionKind>::modify`).
+    pub fn modify(self, modifier: &mut Modifier) -> Program {
+        Program::from(self.global.into_iter().map(|statement| statement.modify(modifier)).collect::<BlockStatement>())
+    }
+}
+
+impl Node<StatementKind> {
+    pub fn modify(self, modifier: &mut Modifier) -> Statement {
+        let kind = match self.kind {
+            StatementKind::Expr(expression) => StatementKind::Expr(expression.modify(modifier)),
+            StatementKind::Let{ name, value, doc } => StatementKind::Let{ name, value: value.modify(modifier), doc },
+            StatementKind::Return(expression) => StatementKind::Return(expression.modify(modifier)),
+        };
+        Node::new(kind, self.span)
+    }
+}
+
+impl Node<ExpressionKind> {
+    /// Rebuilds the expression from the bottom up: the subexpressions are
+    /// modified first, then the expression made of the modified
+    /// subexpressions is passed to `modifier`, whose result replaces it.
+    pub fn modify(self, modifier: &mut Modifier) -> Expression {
+        let boxed = |expression: Box<Expression>, modifier: &mut Modifier| Box::new(expression.modify(modifier));
+        let kind = match self.kind {
+            ExpressionKind::Function{ params, body } => ExpressionKind::Function{ params, body: boxed(body, modifier) },
+            ExpressionKind::MacroLiteral{ params, body } => ExpressionKind::MacroLiteral{ params, body: boxed(body, modifier) },
+            ExpressionKind::PrefixExpression{ operator, right } => {
+                ExpressionKind::PrefixExpression{ operator, right: boxed(right, modifier) }
+            },
+            ExpressionKind::InfixExpression{ left, op, right } => {
+                let left = boxed(left, modifier);
+                ExpressionKind::InfixExpression{ left, op, right: boxed(right, modifier) }
+            },
+            ExpressionKind::PostfixExpression{ right, op } => ExpressionKind::PostfixExpression{ right: boxed(right, modifier), op },
+            ExpressionKind::IfExpression{ condition, consequence, alternative } => ExpressionKind::IfExpression{
+                condition: boxed(condition, modifier),
+                consequence: Box::new(consequence.modify(modifier)),
+                alternative: Box::new(alternative.modify(modifier)),
+            },
+            ExpressionKind::CallExpression{ lambda, parameters } => {
+                let lambda = boxed(lambda, modifier);
+                let parameters = parameters.into_iter().map(|parameter| parameter.modify(modifier)).collect();
+                ExpressionKind::CallExpression{ lambda, parameters }
+            },
+            ExpressionKind::ArrayLiteral(elements) => {
+                ExpressionKind::ArrayLiteral(elements.into_iter().map(|element| element.modify(modifier)).collect())
+            },
+            ExpressionKind::HashLiteral(pairs) => ExpressionKind::HashLiteral(
+                pairs.into_iter().map(|(key, value)| (key.modify(modifier), value.modify(modifier))).collect()
+            ),
+            ExpressionKind::IndexExpression{ left, index } => {
+                let left = boxed(left, modifier);
+                ExpressionKind::IndexExpression{ left, index: boxed(index, modifier) }
+            },
+            ExpressionKind::BlockExpression(statements) => ExpressionKind::BlockExpression(
+                statements.into_iter().map(|statement| statement.modify(modifier)).collect()
+            ),
+            kind => kind,
+        };
+        modifier(Node::new(kind, self.span))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(String::from(input)).parse_program().unwrap()
+    }
+
+    #[test]
+    pub fn test_display() {
+        let tests = [
+            ("let x = -a * b + c[1];", "let x = (((-a) * b) + (c[1]))"),
+            ("return add(1, 2.5);", "return add(1, 2.5)"),
+            (r#"if (x) { "a" } else { let y = [1, 2]; {y: true} }"#, r#"if (x) { "a" } else { let y = [1, 2]; {y: true} }"#),
+            ("if (x) { }", "if (x) {}"),
+            ("fn(a, b) { a }; macro(x) { quote(x) }", "fn(a, b) { a }; macro(x) { quote(x) }"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(parse(input).to_string(), *expected);
+        }
+    }
+
+    #[test]
+    pub fn test_modify() {
+        let one_to_two = &mut |expression: Expression| match expression.kind {
+            ExpressionKind::Int(1) => Node::new(ExpressionKind::Int(2), expression.span),
+            _ => expression,
+        };
+        let tests = [
+            ("1", "2"),
+            ("1 + 2; -1", "(2 + 2); (-2)"),
+            ("[1, 2][1]", "([2, 2][2])"),
+            ("if (1) { 1 } else { 1 }", "if (2) { 2 } else { 2 }"),
+            ("let x = 1; return 1;", "let x = 2; return 2"),
+            ("fn(x) { 1 }(1); macro(x) { 1 }", "fn(x) { 2 }(2); macro(x) { 2 }"),
+            ("{1: 1}", "{2: 2}"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(parse(input).modify(one_to_two).to_string(), *expected);
+        }
+
+        let mut visited = Vec::new();
+        parse("f(1 + 2)").modify(&mut |expression| {
+            visited.push(expression.to_string());
+            expression
+        });
+        assert_eq!(visited, vec!["f", "1", "2", "(1 + 2)", "f((1 + 2))"]);
+    }
+}
@@ -0,0 +1,253 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{ self, IsTerminal };
+use std::process;
+
+use core_dev::compiler;
+use core_dev::environment::Environment;
+use core_dev::interpreter::{ Error, Evaluation, Interpreter };
+use core_dev::macro_expansion;
+use core_dev::parser::Parser;
+use core_dev::repl;
+use core_dev::vm;
+use core_stable::diagnostics::{ Diagnostic, Renderer };
+use core_stable::lexer::Lexer;
+
+const USAGE: &str = "\
+usage: monkey [--vm] [FILE | -e PROGRAM | -]
+
+  FILE          run the script FILE (usually a `.mk` file)
+  -e PROGRAM    run PROGRAM
+  -             run the program read from the standard input
+  --vm          compile the program to bytecode and run it on the VM, which
+                is faster than walking the tree
+
+Without arguments, starts the REPL, or runs the standard input if it is not a terminal.";
+
+/// The problems of a program, with what is needed to render them.
+struct Report {
+    file_name: String,
+    /// The source quoted under the problems, which is not kept when read
+    /// from the standard input.
+    source: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    fn render(&self, renderer: Renderer) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| match &self.source {
+                Some(source) => renderer.render(diagnostic, &self.file_name, source),
+                None => format!(
+                    "{}: {}:{}:{}: {}\n",
+                    if diagnostic.is_error() { "error" } else { "warning" },
+                    self.file_name,
+                    diagnostic.span.line,
+                    diagnostic.span.column,
+                    diagnostic.message
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Writes the report to the standard error, in color if it is a terminal.
+    fn print(&self) {
+        let renderer = if io::stderr().is_terminal() { Renderer::colored() } else { Renderer::plain() };
+        eprint!("{}", self.render(renderer));
+    }
+}
+
+enum Failure {
+    Usage(String),
+    Io(String, io::Error),
+    Parse(Report),
+    Runtime(Report),
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Failure::Parse(_) | Failure::Runtime(_) => 1,
+            Failure::Usage(_) | Failure::Io(..) => 2,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            Failure::Io(path, err) => write!(f, "cannot read {}: {}", path, err),
+            Failure::Parse(report) | Failure::Runtime(report) => write!(f, "{}", report.render(Renderer::plain())),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(failure) = run(&args) {
+        match &failure {
+            Failure::Parse(report) | Failure::Runtime(report) => report.print(),
+            _ => eprintln!("monkey: {}", failure),
+        }
+        process::exit(failure.exit_code());
+    }
+}
+
+/// How programs are run.
+#[derive(Clone, Copy)]
+enum Backend {
+    Eval,
+    Vm,
+}
+
+/// Where programs are read from.
+enum Input {
+    Source(String),
+    /// Lexed as it is read, without loading it whole first.
+    Stdin,
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let (backend, args) = match args {
+        [flag, args @ ..] if flag == "--vm" => (Backend::Vm, args),
+        args => (Backend::Eval, args),
+    };
+    let run_source = |file_name: &str, input: Input| run_source(file_name, input, backend);
+    match args {
+        [] if io::stdin().is_terminal() => match backend {
+            Backend::Eval => {
+                repl::start();
+                Ok(())
+            },
+            Backend::Vm => Err(Failure::Usage(String::from("--vm expects a program"))),
+        },
+        [] => run_source("<stdin>", Input::Stdin),
+        [flag] if flag == "-" => run_source("<stdin>", Input::Stdin),
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        [flag, program] if flag == "-e" => run_source("<command line>", Input::Source(program.clone())),
+        [flag] if flag == "-e" => Err(Failure::Usage(String::from("-e expects a program"))),
+        [path] if !path.starts_with('-') => {
+            let source = fs::read_to_string(path).map_err(|err| Failure::Io(path.clone(), err))?;
+            run_source(path, Input::Source(source))
+        },
+        _ => Err(Failure::Usage(format!("unexpected arguments: {}", args.join(" ")))),
+    }
+}
+
+fn run_source(file_name: &str, input: Input, backend: Backend) -> Result<(), Failure> {
+    let source = match &input {
+        Input::Source(source) => Some(source.clone()),
+        Input::Stdin => None,
+    };
+    let report = |diagnostics| Report {
+        file_name: String::from(file_name),
+        source: source.clone(),
+        diagnostics,
+    };
+    let result = match (backend, &input) {
+        (Backend::Eval, Input::Source(source)) => Interpreter::new().eval_str(source),
+        (Backend::Eval, Input::Stdin) => Interpreter::new().eval_reader(io::stdin().lock()),
+        (Backend::Vm, Input::Source(source)) => compile_and_run(Parser::new(source.clone())),
+        (Backend::Vm, Input::Stdin) => compile_and_run(Parser::from(Lexer::from_reader(io::stdin().lock()))),
+    };
+    match result {
+        Ok(evaluation) => {
+            if !evaluation.warnings.is_empty() {
+                report(evaluation.warnings).print();
+            }
+            Ok(())
+        },
+        Err(Error::Parse(diagnostics)) => Err(Failure::Parse(report(diagnostics))),
+        Err(Error::Runtime(err)) => Err(Failure::Runtime(report(vec![Diagnostic::from(&err)]))),
+        Err(err) => unreachable!("{}", err),
+    }
+}
+
+/// The problems found by the compiler are reported as parse errors. The
+/// macros are expanded beforehand, as with the evaluator.
+fn compile_and_run(mut parser: Parser) -> Result<Evaluation, Error> {
+    let mut program = parser.parse_program().map_err(Error::Parse)?;
+    let warnings = parser.diagnostics();
+    let macros = Environment::new();
+    macro_expansion::define_macros(&mut program, &macros);
+    let program = macro_expansion::expand_macros(program, &macros).map_err(Error::Runtime)?;
+    let bytecode = compiler::compile(&program).map_err(|diagnostic| Error::Parse(vec![diagnostic]))?;
+    let value = vm::run(bytecode).map_err(Error::Runtime)?;
+    Ok(Evaluation { value, warnings })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use core_stable::span::Span;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+    #[test]
+    pub fn test_exit_codes() {
+        let tests = [
+            (args(&["-e", "let x = 1 + 2;"]), 0),
+            (args(&["-e", "1 + true"]), 1),
+            (args(&["-e", "let = 2;"]), 1),
+            (args(&["-e"]), 2),
+            (args(&["--unknown"]), 2),
+            (args(&["does/not/exist.mk"]), 2),
+            (args(&["--vm", "-e", "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(10)"]), 0),
+            (args(&["--vm", "-e", "1 + true"]), 1),
+            (args(&["--vm", "-e", "let = 2;"]), 1),
+            (args(&["--vm", "-e", "let twice = macro(x) { quote(unquote(x) * 2) }; twice(21)"]), 0),
+            (args(&["--vm", "-e", "quote(1)"]), 1),
+            (args(&["--vm", "--vm"]), 2),
+        ];
+
+        for (args, expected) in tests.iter() {
+            let code = match run(args) {
+                Ok(()) => 0,
+                Err(failure) => failure.exit_code()
+            };
+            assert_eq!(code, *expected, "monkey {}", args.join(" "));
+        }
+    }
+
+    #[test]
+    pub fn test_run_file() {
+        let path = env::temp_dir().join(format!("monkey-test-{}.mk", process::id()));
+        fs::write(&path, "let add = fn(x, y) { x + y };\nadd(1, 2);\n").unwrap();
+        let result = run(&[path.to_string_lossy().into_owned()]);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    pub fn test_report() {
+        let failure = run(&args(&["-e", "let x = 1;\nlet y = x + true;"])).unwrap_err();
+        assert_eq!(
+            failure.to_string(),
+            "\
+error: type mismatch: INTEGER + BOOLEAN
+ --> <command line>:2:9
+  |
+2 | let y = x + true;
+  |         ^^^^^^^^
+"
+        );
+        let vm_failure = run(&args(&["--vm", "-e", "let x = 1;\nlet y = x + true;"])).unwrap_err();
+        assert_eq!(vm_failure.to_string(), failure.to_string());
+
+        let stdin = Report {
+            file_name: String::from("<stdin>"),
+            source: None,
+            diagnostics: vec![Diagnostic::error("expected an expression, found `;`", Span::new(8, 1, 1, 9))],
+        };
+        assert_eq!(stdin.render(Renderer::plain()), "error: <stdin>:1:9: expected an expression, found `;`\n");
+    }
+}
@@ -0,0 +1,350 @@
+use super::object::{ Arity, Builtin, HashKey, Object, RuntimeError };
+
+type Function = fn(Vec<Object>) -> Object;
+
+/// The evaluator checks the arity before calling a builtin, so that they can
+/// index their arguments.
+const BUILTINS: &[(&str, Arity, Function)] = &[
+    ("puts", Arity::AtLeast(0), puts),
+    ("len", Arity::Exactly(1), len),
+    ("first", Arity::Exactly(1), first),
+    ("last", Arity::Exactly(1), last),
+    ("rest", Arity::Exactly(1), rest),
+    ("push", Arity::Exactly(2), push),
+    ("type", Arity::Exactly(1), type_of),
+    ("str", Arity::Exactly(1), str),
+    ("int", Arity::Exactly(1), int),
+    ("float", Arity::Exactly(1), float),
+    ("keys", Arity::Exactly(1), keys),
+    ("values", Arity::Exactly(1), values),
+    ("contains", Arity::Exactly(2), contains),
+    ("range", Arity::Between(1, 2), range),
+    ("split", Arity::Between(1, 2), split),
+    ("join", Arity::Between(1, 2), join),
+    ("upper", Arity::Exactly(1), upper),
+    ("lower", Arity::Exactly(1), lower),
+];
+
+/// The builtin named `name`, which identifiers resolve to when they are not
+/// bound in the environment.
+pub fn lookup(name: &str) -> Option<Builtin> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _, _)| *builtin == name)
+        .map(|(name, arity, func)| Builtin::new(name, *arity, *func))
+}
+
+/// The names of every builtin.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _, _)| *name)
+}
+
+macro_rules! error {
+    ( $($arg:tt)* ) => {
+        Object::Error(RuntimeError::from(format!($($arg)*)))
+    };
+}
+
+/// Error for the argument `got` of the builtin `name`, which should have been
+/// of the type `expected`.
+fn type_error(name: &str, expected: &str, got: &Object) -> Object {
+    error!("wrong argument type for `{}`: expected {}, got {}", name, expected, got.type_name())
+}
+
+fn puts(args: Vec<Object>) -> Object {
+    for arg in args {
+        println!("{}", arg);
+    }
+    Object::Unit
+}
+
+/// The number of characters of a string, of elements of an array, or of pairs
+/// of a hash.
+fn len(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::String(value) => Object::Int(value.chars().count() as isize),
+        Object::Array(elements) => Object::Int(elements.len() as isize),
+        Object::Hash(pairs) => Object::Int(pairs.len() as isize),
+        arg => type_error("len", "STRING, ARRAY or HASH", arg),
+    }
+}
+
+fn first(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) => elements.first().cloned().unwrap_or(Object::Unit),
+        arg => type_error("first", "ARRAY", arg),
+    }
+}
+
+fn last(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) => elements.last().cloned().unwrap_or(Object::Unit),
+        arg => type_error("last", "ARRAY", arg),
+    }
+}
+
+/// Every element but the first one, or `()` for an empty array.
+fn rest(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(elements) if elements.is_empty() => Object::Unit,
+        Object::Array(elements) => Object::Array(elements[1..].to_vec()),
+        arg => type_error("rest", "ARRAY", arg),
+    }
+}
+
+/// A new array, the argument being left untouched.
+fn push(args: Vec<Object>) -> Object {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Object::Array(mut elements)), Some(element)) => {
+            elements.push(element);
+            Object::Array(elements)
+        },
+        (Some(arg), _) => type_error("push", "ARRAY", &arg),
+        _ => Object::Unit,
+    }
+}
+
+fn type_of(args: Vec<Object>) -> Object {
+    Object::String(String::from(args[0].type_name()))
+}
+
+fn str(args: Vec<Object>) -> Object {
+    Object::String(args[0].to_string())
+}
+
+/// Floats are truncated toward zero.
+fn int(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Int(value) => Object::Int(*value),
+        Object::Float(value) if value.is_finite() && value.trunc().abs() < isize::MAX as f64 => {
+            Object::Int(*value as isize)
+        },
+        Object::Float(value) => error!("cannot convert {:?} to INTEGER", value),
+        Object::Boolean(value) => Object::Int(*value as isize),
+        Object::String(value) => match value.trim().parse::<isize>() {
+            Ok(value) => Object::Int(value),
+            Err(_) => error!("cannot convert {:?} to INTEGER", value),
+        },
+        arg => type_error("int", "INTEGER, FLOAT, BOOLEAN or STRING", arg),
+    }
+}
+
+fn float(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Int(value) => Object::Float(*value as f64),
+        Object::Float(value) => Object::Float(*value),
+        Object::String(value) => match value.trim().parse::<f64>() {
+            Ok(value) => Object::Float(value),
+            Err(_) => error!("cannot convert {:?} to FLOAT", value),
+        },
+        arg => type_error("float", "INTEGER, FLOAT or STRING", arg),
+    }
+}
+
+/// The keys of a hash, in its iteration order.
+fn keys(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Hash(pairs) => Object::Array(pairs.keys().cloned().map(Object::from).collect()),
+        arg => type_error("keys", "HASH", arg),
+    }
+}
+
+/// The values of a hash, in the order of their keys.
+fn values(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Hash(pairs) => Object::Array(pairs.values().cloned().collect()),
+        arg => type_error("values", "HASH", arg),
+    }
+}
+
+/// Whether an array has an element, a hash has a key, or a string has a
+/// substring.
+fn contains(args: Vec<Object>) -> Object {
+    match (&args[0], &args[1]) {
+        (Object::Array(elements), element) => Object::Boolean(elements.contains(element)),
+        (Object::Hash(pairs), key) => match HashKey::from_object(key) {
+            Some(key) => Object::Boolean(pairs.contains_key(&key)),
+            None => error!("unusable as hash key: {}", key.type_name()),
+        },
+        (Object::String(value), Object::String(pattern)) => Object::Boolean(value.contains(pattern.as_str())),
+        (Object::String(_), arg) => type_error("contains", "STRING", arg),
+        (arg, _) => type_error("contains", "ARRAY, HASH or STRING", arg),
+    }
+}
+
+/// The most integers `range` gives, so that a mistake fails instead of
+/// exhausting the memory.
+pub const MAX_RANGE_LEN: usize = 1_000_000;
+
+/// `range(end)` or `range(start, end)`: the integers from `start`, 0 by
+/// default, up to `end` excluded.
+fn range(args: Vec<Object>) -> Object {
+    let bounds = match args.as_slice() {
+        [Object::Int(end)] => (0, *end),
+        [Object::Int(start), Object::Int(end)] => (*start, *end),
+        [Object::Int(_), arg] | [arg, ..] => return type_error("range", "INTEGER", arg),
+        [] => return Object::Unit,
+    };
+    let len = bounds.1.saturating_sub(bounds.0).max(0) as usize;
+    if len > MAX_RANGE_LEN {
+        return error!("range too long: {} integers, the limit is {}", len, MAX_RANGE_LEN);
+    }
+    Object::Array((bounds.0..bounds.1).map(Object::Int).collect())
+}
+
+/// Splits on whitespaces by default, or between every character when the
+/// separator is empty.
+fn split(args: Vec<Object>) -> Object {
+    let parts: Vec<&str> = match args.as_slice() {
+        [Object::String(value)] => value.split_whitespace().collect(),
+        [Object::String(value), Object::String(separator)] if separator.is_empty() => {
+            value.char_indices().map(|(i, ch)| &value[i..i + ch.len_utf8()]).collect()
+        },
+        [Object::String(value), Object::String(separator)] => value.split(separator.as_str()).collect(),
+        [Object::String(_), arg] | [arg, ..] => return type_error("split", "STRING", arg),
+        [] => return Object::Unit,
+    };
+    Object::Array(parts.into_iter().map(|part| Object::String(String::from(part))).collect())
+}
+
+/// Joins the elements of an array, written as `str` does, with an optional
+/// separator.
+fn join(args: Vec<Object>) -> Object {
+    let separator = match args.get(1) {
+        Some(Object::String(separator)) => separator.as_str(),
+        Some(arg) => return type_error("join", "STRING", arg),
+        None => "",
+    };
+    match &args[0] {
+        Object::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(Object::to_string).collect();
+            Object::String(elements.join(separator))
+        },
+        arg => type_error("join", "ARRAY", arg),
+    }
+}
+
+fn upper(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::String(value) => Object::String(value.to_uppercase()),
+        arg => type_error("upper", "STRING", arg),
+    }
+}
+
+fn lower(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::String(value) => Object::String(value.to_lowercase()),
+        arg => type_error("lower", "STRING", arg),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::eval::eval_program;
+    use crate::parser::Parser;
+
+    fn test_eval(input: &str) -> Object {
+        let program = Parser::new(String::from(input)).parse_program().unwrap();
+        eval_program(&program, &Environment::new())
+    }
+
+    fn string(value: &str) -> Object {
+        Object::String(String::from(value))
+    }
+
+    fn ints(values: &[isize]) -> Object {
+        Object::Array(values.iter().cloned().map(Object::Int).collect())
+    }
+
+    fn strings(values: &[&str]) -> Object {
+        Object::Array(values.iter().cloned().map(string).collect())
+    }
+
+    #[test]
+    pub fn test_builtins() {
+        let tests = [
+            (r#"len("")"#, Object::Int(0)),
+            (r#"len("héllo")"#, Object::Int(5)),
+            ("len([1, 2, 3])", Object::Int(3)),
+            (r#"len({"a": 1})"#, Object::Int(1)),
+            ("first([1, 2, 3])", Object::Int(1)),
+            ("first([])", Object::Unit),
+            ("last([1, 2, 3])", Object::Int(3)),
+            ("last([])", Object::Unit),
+            ("rest([1, 2, 3])", ints(&[2, 3])),
+            ("rest([1])", ints(&[])),
+            ("rest([])", Object::Unit),
+            ("let a = [1]; let b = push(a, 2); [a, b]", Object::Array(vec![ints(&[1]), ints(&[1, 2])])),
+            ("type(1)", string("INTEGER")),
+            ("type(len)", string("BUILTIN")),
+            (r#"type({})"#, string("HASH")),
+            ("str(1.5)", string("1.5")),
+            (r#"str("a")"#, string("a")),
+            (r#"str([1, "a"])"#, string(r#"[1, "a"]"#)),
+            ("int(-2.7)", Object::Int(-2)),
+            (r#"int(" 42 ")"#, Object::Int(42)),
+            ("int(true)", Object::Int(1)),
+            ("float(2)", Object::Float(2.0)),
+            (r#"float("1e3")"#, Object::Float(1000.0)),
+            (r#"keys({"b": 1, "a": 2, 3: 3})"#, Object::Array(vec![Object::Int(3), string("a"), string("b")])),
+            (r#"values({"b": 1, "a": 2})"#, ints(&[2, 1])),
+            ("contains([1, 2], 2)", Object::Boolean(true)),
+            ("contains([1, 2], 3)", Object::Boolean(false)),
+            (r#"contains({"a": 1}, "a")"#, Object::Boolean(true)),
+            (r#"contains("monkey", "key")"#, Object::Boolean(true)),
+            ("range(3)", ints(&[0, 1, 2])),
+            ("range(2, 5)", ints(&[2, 3, 4])),
+            ("range(5, 2)", ints(&[])),
+            (r#"split("a b  c")"#, strings(&["a", "b", "c"])),
+            (r#"split("a,b,,c", ",")"#, strings(&["a", "b", "", "c"])),
+            (r#"split("hé", "")"#, strings(&["h", "é"])),
+            (r#"join(["a", 1, true])"#, string("a1true")),
+            (r#"join(split("a b c"), "-")"#, string("a-b-c")),
+            (r#"upper("Straße")"#, string("STRASSE")),
+            (r#"lower("MoNKEY")"#, string("monkey")),
+            ("puts()", Object::Unit),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(test_eval(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_builtin_errors() {
+        let tests = [
+            ("len(1)", "wrong argument type for `len`: expected STRING, ARRAY or HASH, got INTEGER"),
+            ("len()", "wrong number of arguments to `len`: expected 1, got 0"),
+            (r#"len("one", "two")"#, "wrong number of arguments to `len`: expected 1, got 2"),
+            ("range()", "wrong number of arguments to `range`: expected 1 or 2, got 0"),
+            ("first(1)", "wrong argument type for `first`: expected ARRAY, got INTEGER"),
+            ("push(1, 1)", "wrong argument type for `push`: expected ARRAY, got INTEGER"),
+            (r#"int("abc")"#, r#"cannot convert "abc" to INTEGER"#),
+            (r#"float("")"#, r#"cannot convert "" to FLOAT"#),
+            ("int([])", "wrong argument type for `int`: expected INTEGER, FLOAT, BOOLEAN or STRING, got ARRAY"),
+            (r#"range(1, "2")"#, "wrong argument type for `range`: expected INTEGER, got STRING"),
+            ("range(1000000000000000000)", "range too long: 1000000000000000000 integers, the limit is 1000000"),
+            ("range(-9223372036854775807, 9223372036854775807)", "range too long: 9223372036854775807 integers, the limit is 1000000"),
+            (r#"contains({}, [])"#, "unusable as hash key: ARRAY"),
+            (r#"contains("abc", 1)"#, "wrong argument type for `contains`: expected STRING, got INTEGER"),
+            (r#"join([], 1)"#, "wrong argument type for `join`: expected STRING, got INTEGER"),
+            (r#"split("a", 1)"#, "wrong argument type for `split`: expected STRING, got INTEGER"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(test_eval(input), Object::Error(RuntimeError::from(*expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_registry() {
+        for name in names() {
+            assert_eq!(lookup(name).map(|builtin| builtin.name), Some(String::from(name)));
+        }
+        assert_eq!(names().count(), 18);
+        assert!(lookup("nope").is_none());
+    }
+}
@@ -0,0 +1,642 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::process;
+
+use super::ast;
+use super::interpreter::Error;
+use super::parser::Parser;
+use core_stable::diagnostics::{ Diagnostic, Severity };
+use core_stable::span::Span;
+
+/// The first bytes of every cache file.
+pub const MAGIC: &[u8; 4] = b"MKYC";
+/// Bumped whenever the encoding of the tree changes.
+pub const VERSION: u16 = 4;
+
+/// Magic, version, source hash, payload length and payload checksum.
+const HEADER_LEN: usize = 4 + 2 + 8 + 8 + 8;
+
+/// Why a cache file cannot be loaded.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum CacheError {
+    /// Not a cache file.
+    BadMagic,
+    /// Written by another version of the format.
+    UnsupportedVersion(u16),
+    /// Written for another source.
+    Stale,
+    Truncated,
+    Corrupt(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::BadMagic => write!(f, "not a Monkey cache file"),
+            CacheError::UnsupportedVersion(version) => {
+                write!(f, "unsupported cache format version {} (expected {})", version, VERSION)
+            },
+            CacheError::Stale => write!(f, "the cache was written for another source"),
+            CacheError::Truncated => write!(f, "the cache file is truncated"),
+            CacheError::Corrupt(reason) => write!(f, "the cache file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+/// FNV-1a, which unlike the hasher of the standard library is the same from
+/// one Rust release to the next.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Parses `source`, or loads the tree cached in `cache` when it was written
+/// for the same source, along with the warnings of the parser. A missing,
+/// stale or outdated cache is (re)written; a cache that cannot be read is an
+/// error rather than silently replaced.
+pub fn parse_program<P: AsRef<Path>>(source: &str, cache: P) -> Result<(ast::Program, Vec<Diagnostic>), Error> {
+    let cache = cache.as_ref();
+    match fs::read(cache) {
+        Ok(bytes) => match decode(&bytes, source) {
+            Ok(parsed) => return Ok(parsed),
+            Err(CacheError::Stale) | Err(CacheError::UnsupportedVersion(_)) => (),
+            Err(err) => return Err(Error::Cache(cache.to_path_buf(), err)),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(Error::Io(cache.to_path_buf(), err)),
+    }
+
+    let mut parser = Parser::new(String::from(source));
+    let program = parser.parse_program().map_err(Error::Parse)?;
+    let warnings = parser.diagnostics();
+    write(cache, &encode(&program, &warnings, source)).map_err(|err| Error::Io(cache.to_path_buf(), err))?;
+    Ok((program, warnings))
+}
+
+/// The file a cache is written to before being renamed into place.
+fn temporary_path(cache: &Path) -> PathBuf {
+    let mut path = cache.as_os_str().to_owned();
+    path.push(format!(".{}.tmp", process::id()));
+    PathBuf::from(path)
+}
+
+/// Writes to a temporary file in the same directory, then renames it to
+/// `cache`, so that an interrupted write or a concurrent reader never sees a
+/// partial cache.
+fn write(cache: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = temporary_path(cache);
+    let result = fs::write(&temporary, bytes).and_then(|_| fs::rename(&temporary, cache));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Serializes `program` and the `warnings` of its parser, parsed from
+/// `source`.
+pub fn encode(program: &ast::Program, warnings: &[Diagnostic], source: &str) -> Vec<u8> {
+    let mut payload = Writer::default();
+    payload.block(&program.global);
+    payload.diagnostics(warnings);
+    let payload = payload.bytes;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+    bytes.extend_from_slice(&hash(source.as_bytes()).to_be_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&hash(&payload).to_be_bytes());
+    bytes.extend(payload);
+    bytes
+}
+
+/// Deserializes a program and its warnings encoded for `source`.
+pub fn decode(bytes: &[u8], source: &str) -> Result<(ast::Program, Vec<Diagnostic>), CacheError> {
+    if !bytes.starts_with(&MAGIC[..bytes.len().min(MAGIC.len())]) {
+        return Err(CacheError::BadMagic);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(CacheError::Truncated);
+    }
+    let u64_at = |offset: usize| {
+        let mut value = [0; 8];
+        value.copy_from_slice(&bytes[offset..offset + 8]);
+        u64::from_be_bytes(value)
+    };
+    let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(CacheError::UnsupportedVersion(version));
+    }
+    if u64_at(6) != hash(source.as_bytes()) {
+        return Err(CacheError::Stale);
+    }
+    let payload = &bytes[HEADER_LEN..];
+    if (payload.len() as u64) < u64_at(14) {
+        return Err(CacheError::Truncated);
+    }
+    if payload.len() as u64 > u64_at(14) {
+        return Err(CacheError::Corrupt(String::from("trailing bytes after the program")));
+    }
+    if hash(payload) != u64_at(22) {
+        return Err(CacheError::Corrupt(String::from("checksum mismatch")));
+    }
+
+    let mut reader = Reader { bytes: payload, position: 0 };
+    let global = reader.block()?;
+    let warnings = reader.diagnostics()?;
+    Ok((ast::Program::from(global), warnings))
+}
+
+/// Unsigned integers are written as LEB128, in 7-bit groups.
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn uint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.uint(value.len() as u64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn optional_string(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.bytes.push(1);
+                self.string(value);
+            },
+            None => self.bytes.push(0),
+        }
+    }
+
+    fn span(&mut self, span: Span) {
+        for value in &[span.offset, span.len, span.line, span.column] {
+            self.uint(*value as u64);
+        }
+    }
+
+    fn diagnostics(&mut self, diagnostics: &[Diagnostic]) {
+        self.uint(diagnostics.len() as u64);
+        for diagnostic in diagnostics {
+            self.bytes.push(match diagnostic.severity {
+                Severity::Error => 0,
+                Severity::Warning => 1,
+            });
+            self.string(&diagnostic.message);
+            self.span(diagnostic.span);
+            self.optional_string(diagnostic.label.as_deref());
+            self.optional_string(diagnostic.help.as_deref());
+        }
+    }
+
+    fn block(&mut self, statements: &[ast::Statement]) {
+        self.uint(statements.len() as u64);
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[ast::Expression]) {
+        self.uint(expressions.len() as u64);
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn statement(&mut self, statement: &ast::Statement) {
+        match &statement.kind {
+            ast::StatementKind::Expr(expression) => {
+                self.bytes.push(0);
+                self.expression(expression);
+            },
+            ast::StatementKind::Let{ name, value, doc } => {
+                self.bytes.push(1);
+                self.string(name);
+                self.expression(value);
+                self.optional_string(doc.as_deref());
+            },
+            ast::StatementKind::Return(expression) => {
+                self.bytes.push(2);
+                self.expression(expression);
+            },
+        }
+        self.span(statement.span);
+    }
+
+    fn operator(&mut self, operator: &ast::Operator) {
+        let (kind, personalised) = match operator {
+            ast::Operator::Prefix(ast::PrefixOperator::Plus) => (0, None),
+            ast::Operator::Prefix(ast::PrefixOperator::Minus) => (1, None),
+            ast::Operator::Prefix(ast::PrefixOperator::Bang) => (2, None),
+            ast::Operator::Prefix(ast::PrefixOperator::Personnalised(op)) => (3, Some(op)),
+            ast::Operator::Infix(ast::InfixOperator::Plus) => (4, None),
+            ast::Operator::Infix(ast::InfixOperator::Minus) => (5, None),
+            ast::Operator::Infix(ast::InfixOperator::Mul) => (6, None),
+            ast::Operator::Infix(ast::InfixOperator::Div) => (7, None),
+            ast::Operator::Infix(ast::InfixOperator::Eq) => (8, None),
+            ast::Operator::Infix(ast::InfixOperator::NotEq) => (9, None),
+            ast::Operator::Infix(ast::InfixOperator::GT) => (10, None),
+            ast::Operator::Infix(ast::InfixOperator::LT) => (11, None),
+            ast::Operator::Infix(ast::InfixOperator::GTE) => (12, None),
+            ast::Operator::Infix(ast::InfixOperator::LTE) => (13, None),
+            ast::Operator::Infix(ast::InfixOperator::Personalised(op)) => (14, Some(op)),
+            ast::Operator::Suffix(ast::SuffixOperator::Bang) => (15, None),
+            ast::Operator::Suffix(ast::SuffixOperator::Personnalised(op)) => (16, Some(op)),
+        };
+        self.bytes.push(kind);
+        if let Some(op) = personalised {
+            self.string(op);
+        }
+    }
+
+    fn expression(&mut self, expression: &ast::Expression) {
+        match &expression.kind {
+            ast::ExpressionKind::Ident(name) => {
+                self.bytes.push(0);
+                self.string(name);
+            },
+            ast::ExpressionKind::Int(value) => {
+                self.bytes.push(1);
+                // Zigzag, so that the small negative integers stay short.
+                let value = *value as i64;
+                self.uint(((value << 1) ^ (value >> 63)) as u64);
+            },
+            ast::ExpressionKind::Float(value) => {
+                self.bytes.push(2);
+                self.bytes.extend_from_slice(&value.to_bits().to_be_bytes());
+            },
+            ast::ExpressionKind::String(value) => {
+                self.bytes.push(3);
+                self.string(value);
+            },
+            ast::ExpressionKind::Boolean(value) => self.bytes.push(if *value { 5 } else { 4 }),
+            ast::ExpressionKind::Function{ params, body } => {
+                self.bytes.push(6);
+                self.uint(params.len() as u64);
+                for param in params {
+                    self.string(param);
+                }
+                self.expression(body);
+            },
+            ast::ExpressionKind::PrefixExpression{ operator, right } => {
+                self.bytes.push(7);
+                self.operator(operator);
+                self.expression(right);
+            },
+            ast::ExpressionKind::InfixExpression{ left, op, right } => {
+                self.bytes.push(8);
+                self.expression(left);
+                self.operator(op);
+                self.expression(right);
+            },
+            ast::ExpressionKind::PostfixExpression{ right, op } => {
+                self.bytes.push(9);
+                self.expression(right);
+                self.operator(op);
+            },
+            ast::ExpressionKind::IfExpression{ condition, consequence, alternative } => {
+                self.bytes.push(10);
+                self.expression(condition);
+                self.statement(consequence);
+                self.statement(alternative);
+            },
+            ast::ExpressionKind::CallExpression{ lambda, parameters } => {
+                self.bytes.push(11);
+                self.expression(lambda);
+                self.expressions(parameters);
+            },
+            ast::ExpressionKind::ArrayLiteral(elements) => {
+                self.bytes.push(12);
+                self.expressions(elements);
+            },
+            ast::ExpressionKind::HashLiteral(pairs) => {
+                self.bytes.push(13);
+                self.uint(pairs.len() as u64);
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            },
+            ast::ExpressionKind::IndexExpression{ left, index } => {
+                self.bytes.push(14);
+                self.expression(left);
+                self.expression(index);
+            },
+            ast::ExpressionKind::BlockExpression(statements) => {
+                self.bytes.push(15);
+                self.block(statements);
+            },
+            ast::ExpressionKind::Unit => self.bytes.push(16),
+            ast::ExpressionKind::MacroLiteral{ params, body } => {
+                self.bytes.push(17);
+                self.uint(params.len() as u64);
+                for param in params {
+                    self.string(param);
+                }
+                self.expression(body);
+            },
+        }
+        self.span(expression.span);
+    }
+}
+
+/// Reads what `Writer` wrote, failing on the first malformed byte.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+fn corrupt<T>(reason: String) -> Result<T, CacheError> {
+    Err(CacheError::Corrupt(reason))
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, CacheError> {
+        match self.bytes.get(self.position) {
+            Some(byte) => {
+                self.position += 1;
+                Ok(*byte)
+            },
+            None => corrupt(String::from("unexpected end of the program")),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, CacheError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        corrupt(String::from("integer too large"))
+    }
+
+    /// A length, which cannot exceed the remaining bytes as every item takes
+    /// at least one.
+    fn len(&mut self) -> Result<usize, CacheError> {
+        let len = self.uint()?;
+        if len > (self.bytes.len() - self.position) as u64 {
+            return corrupt(format!("length {} past the end of the program", len));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, CacheError> {
+        let len = self.len()?;
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => corrupt(String::from("invalid UTF-8 in a string")),
+        }
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, CacheError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.string().map(Some),
+            tag => corrupt(format!("unknown option tag {}", tag)),
+        }
+    }
+
+    fn span(&mut self) -> Result<Span, CacheError> {
+        let mut values = [0; 4];
+        for value in values.iter_mut() {
+            *value = self.uint()? as usize;
+        }
+        Ok(Span::new(values[0], values[1], values[2], values[3]))
+    }
+
+    fn diagnostics(&mut self) -> Result<Vec<Diagnostic>, CacheError> {
+        let len = self.len()?;
+        (0..len).map(|_| {
+            let severity = match self.byte()? {
+                0 => Severity::Error,
+                1 => Severity::Warning,
+                tag => return corrupt(format!("unknown severity tag {}", tag)),
+            };
+            Ok(Diagnostic {
+                severity,
+                message: self.string()?,
+                span: self.span()?,
+                label: self.optional_string()?,
+                help: self.optional_string()?,
+            })
+        }).collect()
+    }
+
+    fn block(&mut self) -> Result<ast::BlockStatement, CacheError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.statement()).collect()
+    }
+
+    fn expressions(&mut self) -> Result<Vec<ast::Expression>, CacheError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.expression()).collect()
+    }
+
+    fn statement(&mut self) -> Result<ast::Statement, CacheError> {
+        let kind = match self.byte()? {
+            0 => ast::StatementKind::Expr(self.expression()?),
+            1 => ast::StatementKind::Let{ name: self.string()?, value: self.expression()?, doc: self.optional_string()? },
+            2 => ast::StatementKind::Return(self.expression()?),
+            tag => return corrupt(format!("unknown statement tag {}", tag)),
+        };
+        Ok(ast::Node::new(kind, self.span()?))
+    }
+
+    fn operator(&mut self) -> Result<ast::Operator, CacheError> {
+        Ok(match self.byte()? {
+            0 => ast::Operator::Prefix(ast::PrefixOperator::Plus),
+            1 => ast::Operator::Prefix(ast::PrefixOperator::Minus),
+            2 => ast::Operator::Prefix(ast::PrefixOperator::Bang),
+            3 => ast::Operator::Prefix(ast::PrefixOperator::Personnalised(self.string()?)),
+            4 => ast::Operator::Infix(ast::InfixOperator::Plus),
+            5 => ast::Operator::Infix(ast::InfixOperator::Minus),
+            6 => ast::Operator::Infix(ast::InfixOperator::Mul),
+            7 => ast::Operator::Infix(ast::InfixOperator::Div),
+            8 => ast::Operator::Infix(ast::InfixOperator::Eq),
+            9 => ast::Operator::Infix(ast::InfixOperator::NotEq),
+            10 => ast::Operator::Infix(ast::InfixOperator::GT),
+            11 => ast::Operator::Infix(ast::InfixOperator::LT),
+            12 => ast::Operator::Infix(ast::InfixOperator::GTE),
+            13 => ast::Operator::Infix(ast::InfixOperator::LTE),
+            14 => ast::Operator::Infix(ast::InfixOperator::Personalised(self.string()?)),
+            15 => ast::Operator::Suffix(ast::SuffixOperator::Bang),
+            16 => ast::Operator::Suffix(ast::SuffixOperator::Personnalised(self.string()?)),
+            tag => return corrupt(format!("unknown operator tag {}", tag)),
+        })
+    }
+
+    fn expression(&mut self) -> Result<ast::Expression, CacheError> {
+        let kind = match self.byte()? {
+            0 => ast::ExpressionKind::Ident(self.string()?),
+            1 => {
+                let value = self.uint()?;
+                ast::ExpressionKind::Int(((value >> 1) as i64 ^ -((value & 1) as i64)) as isize)
+            },
+            2 => {
+                let mut bits = 0u64;
+                for _ in 0..8 {
+                    bits = bits << 8 | self.byte()? as u64;
+                }
+                ast::ExpressionKind::Float(f64::from_bits(bits))
+            },
+            3 => ast::ExpressionKind::String(self.string()?),
+            4 => ast::ExpressionKind::Boolean(false),
+            5 => ast::ExpressionKind::Boolean(true),
+            6 => {
+                let len = self.len()?;
+                let params = (0..len).map(|_| self.string()).collect::<Result<_, _>>()?;
+                ast::ExpressionKind::Function{ params, body: Box::new(self.expression()?) }
+            },
+            7 => ast::ExpressionKind::PrefixExpression{ operator: self.operator()?, right: Box::new(self.expression()?) },
+            8 => ast::ExpressionKind::InfixExpression{
+                left: Box::new(self.expression()?),
+                op: self.operator()?,
+                right: Box::new(self.expression()?),
+            },
+            9 => ast::ExpressionKind::PostfixExpression{ right: Box::new(self.expression()?), op: self.operator()? },
+            10 => ast::ExpressionKind::IfExpression{
+                condition: Box::new(self.expression()?),
+                consequence: Box::new(self.statement()?),
+                alternative: Box::new(self.statement()?),
+            },
+            11 => ast::ExpressionKind::CallExpression{ lambda: Box::new(self.expression()?), parameters: self.expressions()? },
+            12 => ast::ExpressionKind::ArrayLiteral(self.expressions()?),
+            13 => {
+                let len = self.len()?;
+                let pairs = (0..len)
+                    .map(|_| Ok((self.expression()?, self.expression()?)))
+                    .collect::<Result<_, _>>()?;
+                ast::ExpressionKind::HashLiteral(pairs)
+            },
+            14 => ast::ExpressionKind::IndexExpression{ left: Box::new(self.expression()?), index: Box::new(self.expression()?) },
+            15 => ast::ExpressionKind::BlockExpression(self.block()?),
+            16 => ast::ExpressionKind::Unit,
+            17 => {
+                let len = self.len()?;
+                let params = (0..len).map(|_| self.string()).collect::<Result<_, _>>()?;
+                ast::ExpressionKind::MacroLiteral{ params, body: Box::new(self.expression()?) }
+            },
+            tag => return corrupt(format!("unknown expression tag {}", tag)),
+        };
+        Ok(ast::Node::new(kind, self.span()?))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    const SOURCE: &str = r#"/// Adds `a` and `b`.
+///
+/// Both are numbers.
+let add = fn(a, b) { return a + b; };
+let data = {"name": "monkey", 1: [1.5, -2, true], false: if (false) { 1 }};
+let twice = macro(x) { quote(unquote(x) * 2) };
+if (!(add(1, 2) <= 3)) { data["name"] } else { -9223372036854775807 - 1 }
+"#;
+
+    fn parse(source: &str) -> ast::Program {
+        Parser::new(String::from(source)).parse_program().unwrap()
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        let program = parse(SOURCE);
+        let warnings = vec![
+            Diagnostic::warning("mixed scripts", Span::new(1, 2, 3, 4)).with_label("here").with_help("don't"),
+            Diagnostic::warning("again", Span::new(5, 6, 7, 8)),
+        ];
+        let bytes = encode(&program, &warnings, SOURCE);
+        assert!(bytes.starts_with(MAGIC));
+        let (decoded, decoded_warnings) = decode(&bytes, SOURCE).unwrap();
+        // The spans are not compared by `==`, but are written by `{:?}`.
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", program));
+        assert_eq!(format!("{:?}", decoded_warnings), format!("{:?}", warnings));
+    }
+
+    #[test]
+    pub fn test_invalid_files() {
+        let bytes = encode(&parse(SOURCE), &[], SOURCE);
+        for len in 0..bytes.len() {
+            let err = decode(&bytes[..len], SOURCE).unwrap_err();
+            assert!(err == CacheError::Truncated, "{} bytes: {}", len, err);
+        }
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&corrupt, SOURCE), Err(CacheError::Corrupt(String::from("checksum mismatch"))));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode(&trailing, SOURCE),
+            Err(CacheError::Corrupt(String::from("trailing bytes after the program")))
+        );
+        let mut version = bytes.clone();
+        version[5] += 1;
+        assert_eq!(decode(&version, SOURCE), Err(CacheError::UnsupportedVersion(VERSION + 1)));
+        assert_eq!(decode(b"let x = 1;", SOURCE), Err(CacheError::BadMagic));
+        assert_eq!(decode(&bytes, "let x = 1;"), Err(CacheError::Stale));
+
+        // A well-formed header around a payload which is not a program.
+        let mut payload = bytes[..HEADER_LEN].to_vec();
+        payload[14..22].copy_from_slice(&2u64.to_be_bytes());
+        payload[22..30].copy_from_slice(&hash(&[1, 99]).to_be_bytes());
+        payload.extend_from_slice(&[1, 99]);
+        assert_eq!(decode(&payload, SOURCE).unwrap_err().to_string(), "the cache file is corrupt: unknown statement tag 99");
+    }
+
+    #[test]
+    pub fn test_parse_program() {
+        let path = env::temp_dir().join(format!("monkey-cache-test-{}.mkc", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let (program, warnings) = parse_program(SOURCE, &path).unwrap();
+        assert_eq!(program, parse(SOURCE));
+        assert!(warnings.is_empty());
+        assert_eq!(fs::read(&path).unwrap(), encode(&program, &[], SOURCE));
+        assert!(!temporary_path(&path).exists());
+        assert_eq!(parse_program(SOURCE, &path).unwrap(), (program.clone(), vec![]));
+
+        // A stale cache is replaced.
+        assert_eq!(parse_program("1 + 2", &path).unwrap().0, parse("1 + 2"));
+        assert_eq!(decode(&fs::read(&path).unwrap(), "1 + 2").unwrap().0, parse("1 + 2"));
+
+        // The warnings are loaded with the program.
+        let mixed = "let p\u{430}ypal = 1;";
+        let (_, warnings) = parse_program(mixed, &path).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(parse_program(mixed, &path).unwrap().1, warnings);
+
+        fs::write(&path, &encode(&program, &[], SOURCE)[..HEADER_LEN + 3]).unwrap();
+        let err = parse_program(SOURCE, &path).unwrap_err();
+        assert_eq!(err.to_string(), format!("invalid cache {}: the cache file is truncated", path.display()));
+
+        match parse_program("let = 1;", &path) {
+            Err(Error::Parse(diagnostics)) => assert_eq!(diagnostics.len(), 1),
+            other => panic!("{:?}", other),
+        }
+        fs::remove_file(&path).unwrap();
+
+        // A cache which cannot be written is an error.
+        let missing = env::temp_dir().join(format!("monkey-cache-missing-{}", process::id())).join("cache.mkc");
+        assert!(matches!(parse_program(SOURCE, &missing), Err(Error::Io(..))));
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::Write;
+
+/// The bytecode: an opcode byte followed by its big-endian operands.
+pub type Instructions = Vec<u8>;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Opcode {
+    /// Pushes the constant of index operand 0.
+    Constant,
+    Pop,
+    True,
+    False,
+    Unit,
+
+    /// Infix operators, popping the right operand then the left one.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    LowerThan,
+    GreaterThan,
+    LowerThanOrEqual,
+    GreaterThanOrEqual,
+
+    /// Prefix operators.
+    Minus,
+    Plus,
+    Bang,
+
+    /// Jumps to the absolute position operand 0.
+    Jump,
+    /// Pops the condition, and jumps if it is not truthy.
+    JumpNotTruthy,
+
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    /// Pushes the free variable of index operand 0 of the current closure.
+    GetFree,
+    /// Pushes the closure being executed, for the recursive calls.
+    CurrentClosure,
+    /// Puts aside the cell of the local of index operand 0, for the next
+    /// closure to share.
+    CaptureLocal,
+    /// Puts aside the cell of the free variable of index operand 0 of the
+    /// current closure, for the next closure to share.
+    CaptureFree,
+    /// Pops a value and puts it aside in a new cell, for the next closure.
+    Capture,
+
+    /// Builds an array from the operand 0 elements on top of the stack.
+    Array,
+    /// Builds a hash from the operand 0 key-value pairs on top of the stack.
+    /// An unusable key raises an error pointing at the whole hash literal.
+    Hash,
+    Index,
+
+    /// Calls the function below the operand 0 arguments on top of the stack.
+    Call,
+    ReturnValue,
+    /// Makes a closure of the function constant of index operand 0, capturing
+    /// the last operand 1 cells put aside.
+    Closure,
+}
+
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Unit,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::LowerThan,
+    Opcode::GreaterThan,
+    Opcode::LowerThanOrEqual,
+    Opcode::GreaterThanOrEqual,
+    Opcode::Minus,
+    Opcode::Plus,
+    Opcode::Bang,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::CaptureLocal,
+    Opcode::CaptureFree,
+    Opcode::Capture,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).cloned()
+    }
+
+    /// The width in bytes of each operand.
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash => &[2],
+            Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::GetFree
+            | Opcode::CaptureLocal
+            | Opcode::CaptureFree
+            | Opcode::Call => &[1],
+            Opcode::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+/// Encodes an instruction, or returns `None` if an operand does not fit in
+/// its width.
+pub fn make(op: Opcode, operands: &[usize]) -> Option<Instructions> {
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        if *operand >> (8 * width) != 0 {
+            return None;
+        }
+        for byte in (0..*width).rev() {
+            instruction.push((operand >> (8 * byte)) as u8);
+        }
+    }
+    Some(instruction)
+}
+
+/// Decodes the operands of `op` at the beginning of `instructions`, returning
+/// them with the number of bytes they take.
+pub fn read_operands(op: Opcode, instructions: &[u8]) -> (Vec<usize>, usize) {
+    let mut offset = 0;
+    let mut operands = Vec::with_capacity(op.operand_widths().len());
+    for width in op.operand_widths() {
+        operands.push(read_operand(&instructions[offset..], *width));
+        offset += width;
+    }
+    (operands, offset)
+}
+
+/// Reads a big-endian operand of `width` bytes.
+pub fn read_operand(instructions: &[u8], width: usize) -> usize {
+    instructions[..width].iter().fold(0, |operand, byte| operand << 8 | *byte as usize)
+}
+
+/// One instruction per line, prefixed by its offset:
+///
+/// ```text
+/// 0000 Constant 0
+/// 0003 Closure 1 2
+/// ```
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut ip = 0;
+    while ip < instructions.len() {
+        let op = match Opcode::from_byte(instructions[ip]) {
+            Some(op) => op,
+            None => {
+                let _ = writeln!(out, "{:04} <unknown opcode {}>", ip, instructions[ip]);
+                ip += 1;
+                continue;
+            }
+        };
+        let (operands, read) = read_operands(op, &instructions[ip + 1..]);
+        let _ = write!(out, "{:04} {:?}", ip, op);
+        for operand in operands {
+            let _ = write!(out, " {}", operand);
+        }
+        out.push('\n');
+        ip += 1 + read;
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_make() {
+        let tests = [
+            (Opcode::Constant, vec![65534], vec![Opcode::Constant as u8, 255, 254]),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (Opcode::GetLocal, vec![255], vec![Opcode::GetLocal as u8, 255]),
+            (Opcode::Closure, vec![65534, 255], vec![Opcode::Closure as u8, 255, 254, 255]),
+        ];
+
+        for (op, operands, expected) in tests.iter() {
+            let instruction = make(*op, operands).unwrap();
+            assert_eq!(&instruction, expected);
+            assert_eq!(read_operands(*op, &instruction[1..]), (operands.clone(), instruction.len() - 1));
+        }
+        assert_eq!(make(Opcode::Constant, &[65536]), None);
+        assert_eq!(make(Opcode::Call, &[256]), None);
+    }
+
+    #[test]
+    pub fn test_opcodes() {
+        for (byte, op) in OPCODES.iter().enumerate() {
+            assert_eq!(Opcode::from_byte(byte as u8), Some(*op));
+            assert_eq!(*op as usize, byte);
+        }
+        assert_eq!(Opcode::from_byte(OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    pub fn test_disassemble() {
+        let instructions: Instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535, 255]),
+        ].iter().flat_map(|instruction| instruction.clone().unwrap()).collect();
+        assert_eq!(disassemble(&instructions), "\
+0000 Add
+0001 GetLocal 1
+0003 Constant 2
+0006 Constant 65535
+0009 Closure 65535 255
+");
+    }
+}
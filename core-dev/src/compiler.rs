@@ -0,0 +1,590 @@
+use std::collections::{ HashMap, HashSet };
+use std::mem;
+use std::rc::Rc;
+
+use super::ast;
+use super::code::{ self, Instructions, Opcode };
+use super::object::{ CompiledFunction, Object };
+use core_stable::diagnostics::Diagnostic;
+use core_stable::span::Span;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Scope {
+    Global,
+    Local,
+    /// A local of an enclosing function, captured by the closure.
+    Free,
+    /// The function being defined, for the recursive calls.
+    Function,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Symbol {
+    pub scope: Scope,
+    pub index: usize,
+}
+
+/// The bindings of one function, or of the program for the outermost table.
+#[derive(Default)]
+pub struct SymbolTable {
+    store: HashMap<ast::Identifier, Symbol>,
+    num_definitions: usize,
+    /// The symbols of the enclosing tables captured by this function, in the
+    /// order of their free index.
+    free: Vec<Symbol>,
+    /// The locals defined further on in the function, which the closures
+    /// defined before them can already capture.
+    later: HashSet<ast::Identifier>,
+    /// The slots of the locals of `later` captured before their definition.
+    reserved: HashMap<ast::Identifier, Symbol>,
+    outer: Option<Box<SymbolTable>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        Self {
+            outer: Some(Box::new(outer)),
+            ..Self::default()
+        }
+    }
+
+    /// Declares the locals which `body` defines, so that the functions it
+    /// contains can use them before their definition.
+    pub fn declare(&mut self, body: &ast::Expression) {
+        let_names(body, &mut self.later);
+    }
+
+    /// Binds `name` to a new slot. Redefining a global or a local of the same
+    /// function reuses its slot, so that the functions compiled before the
+    /// redefinition see the new value, as with the evaluator.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        let scope = if self.outer.is_none() { Scope::Global } else { Scope::Local };
+        if let Some(symbol) = self.store.get(name) {
+            if symbol.scope == scope {
+                return *symbol;
+            }
+        }
+        let symbol = match self.reserved.remove(name) {
+            Some(symbol) => symbol,
+            None => self.allocate(scope),
+        };
+        self.store.insert(String::from(name), symbol);
+        symbol
+    }
+
+    fn allocate(&mut self, scope: Scope) -> Symbol {
+        let symbol = Symbol { scope, index: self.num_definitions };
+        self.num_definitions += 1;
+        symbol
+    }
+
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol { scope: Scope::Function, index: 0 };
+        self.store.insert(String::from(name), symbol);
+        symbol
+    }
+
+    fn define_free(&mut self, name: &str, original: Symbol) -> Symbol {
+        self.free.push(original);
+        let symbol = Symbol { scope: Scope::Free, index: self.free.len() - 1 };
+        self.store.insert(String::from(name), symbol);
+        symbol
+    }
+
+    /// Looks `name` up in this table, then in the enclosing ones, capturing
+    /// the locals of the enclosing functions as free variables. The names
+    /// bound nowhere are globals, which may be defined later on, the
+    /// builtins being the initial value of the globals named after them: as
+    /// with the evaluator, a function sees the globals defined after it, and
+    /// a global shadows the builtin of the same name from its definition.
+    pub fn resolve(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            return *symbol;
+        }
+        match &mut self.outer {
+            None => self.define(name),
+            Some(outer) => {
+                let symbol = outer.resolve_enclosed(name);
+                match symbol.scope {
+                    Scope::Global => symbol,
+                    _ => self.define_free(name, symbol),
+                }
+            }
+        }
+    }
+
+    /// Resolves `name` for a function defined in this one. Its body runs
+    /// later on, so it also sees the locals this function defines after it.
+    fn resolve_enclosed(&mut self, name: &str) -> Symbol {
+        if self.store.contains_key(name) || !self.later.contains(name) {
+            return self.resolve(name);
+        }
+        if let Some(symbol) = self.reserved.get(name) {
+            return *symbol;
+        }
+        let symbol = self.allocate(Scope::Local);
+        self.reserved.insert(String::from(name), symbol);
+        symbol
+    }
+
+    /// The names of the globals, by index.
+    fn global_names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.num_definitions];
+        for (name, symbol) in &self.store {
+            if symbol.scope == Scope::Global {
+                names[symbol.index] = name.clone();
+            }
+        }
+        names
+    }
+}
+
+/// Adds the names of the `let` statements of `expression` to `names`,
+/// without entering the functions, whose locals are their own.
+fn let_names(expression: &ast::Expression, names: &mut HashSet<ast::Identifier>) {
+    let statement_names = |statement: &ast::Statement, names: &mut HashSet<ast::Identifier>| match &statement.kind {
+        ast::StatementKind::Let{ name, value, .. } => {
+            names.insert(name.clone());
+            let_names(value, names);
+        },
+        ast::StatementKind::Expr(expression) | ast::StatementKind::Return(expression) => let_names(expression, names),
+    };
+    match &expression.kind {
+        ast::ExpressionKind::PrefixExpression{ right, .. } | ast::ExpressionKind::PostfixExpression{ right, .. } => let_names(right, names),
+        ast::ExpressionKind::InfixExpression{ left, right, .. } | ast::ExpressionKind::IndexExpression{ left, index: right } => {
+            let_names(left, names);
+            let_names(right, names);
+        },
+        ast::ExpressionKind::IfExpression{ condition, consequence, alternative } => {
+            let_names(condition, names);
+            statement_names(consequence, names);
+            statement_names(alternative, names);
+        },
+        ast::ExpressionKind::CallExpression{ lambda, parameters } => {
+            let_names(lambda, names);
+            parameters.iter().for_each(|parameter| let_names(parameter, names));
+        },
+        ast::ExpressionKind::ArrayLiteral(elements) => elements.iter().for_each(|element| let_names(element, names)),
+        ast::ExpressionKind::HashLiteral(pairs) => pairs.iter().for_each(|(key, value)| {
+            let_names(key, names);
+            let_names(value, names);
+        }),
+        ast::ExpressionKind::BlockExpression(statements) => statements.iter().for_each(|statement| statement_names(statement, names)),
+        _ => {},
+    }
+}
+
+/// A compiled program, run by the VM as a function without parameters.
+#[derive(Debug)]
+pub struct Bytecode {
+    pub main: Rc<CompiledFunction>,
+    pub constants: Vec<Object>,
+    /// The names of the globals, by index.
+    pub globals: Vec<String>,
+}
+
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    spans: Vec<(usize, Span)>,
+}
+
+struct Compiler {
+    constants: Vec<Object>,
+    symbols: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+/// Lowers `program` to bytecode. Every statement leaves exactly one value on
+/// the stack, which is popped unless it is the value of its block, so that
+/// the VM gives the same results as the evaluator.
+pub fn compile(program: &ast::Program) -> Result<Bytecode, Diagnostic> {
+    let mut compiler = Compiler {
+        constants: Vec::new(),
+        symbols: SymbolTable::new(),
+        scopes: vec![CompilationScope::default()],
+    };
+    compiler.compile_block(&program.global, Span::default())?;
+    let scope = compiler.scopes.pop().unwrap_or_default();
+    Ok(Bytecode {
+        main: Rc::new(CompiledFunction {
+            instructions: scope.instructions,
+            params: Vec::new(),
+            num_locals: 0,
+            spans: scope.spans,
+        }),
+        constants: compiler.constants,
+        globals: compiler.symbols.global_names(),
+    })
+}
+
+/// The instructions which can raise a runtime error, whose span is kept.
+fn can_fail(op: Opcode) -> bool {
+    matches!(op,
+        Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div
+        | Opcode::Equal | Opcode::NotEqual
+        | Opcode::LowerThan | Opcode::GreaterThan | Opcode::LowerThanOrEqual | Opcode::GreaterThanOrEqual
+        | Opcode::Minus | Opcode::Plus
+        | Opcode::GetGlobal | Opcode::Hash | Opcode::Index | Opcode::Call
+    )
+}
+
+fn too_large(span: Span) -> Diagnostic {
+    Diagnostic::error(String::from("program too large to be compiled"), span)
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("no compilation scope")
+    }
+
+    /// Appends an instruction, returning its offset.
+    fn emit(&mut self, op: Opcode, operands: &[usize], span: Span) -> Result<usize, Diagnostic> {
+        let instruction = code::make(op, operands).ok_or_else(|| too_large(span))?;
+        let scope = self.scope();
+        let offset = scope.instructions.len();
+        if can_fail(op) {
+            scope.spans.push((offset, span));
+        }
+        scope.instructions.extend(instruction);
+        Ok(offset)
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize, span: Span) -> Result<(), Diagnostic> {
+        let scope = self.scope();
+        let target = scope.instructions.len();
+        let op = Opcode::from_byte(scope.instructions[offset]).expect("not a jump");
+        let instruction = code::make(op, &[target]).ok_or_else(|| too_large(span))?;
+        scope.instructions[offset..offset + instruction.len()].copy_from_slice(&instruction);
+        Ok(())
+    }
+
+    fn emit_constant(&mut self, constant: Object, span: Span) -> Result<usize, Diagnostic> {
+        self.constants.push(constant);
+        self.emit(Opcode::Constant, &[self.constants.len() - 1], span)
+    }
+
+    fn load_symbol(&mut self, symbol: Symbol, span: Span) -> Result<usize, Diagnostic> {
+        match symbol.scope {
+            Scope::Global => self.emit(Opcode::GetGlobal, &[symbol.index], span),
+            Scope::Local => self.emit(Opcode::GetLocal, &[symbol.index], span),
+            Scope::Free => self.emit(Opcode::GetFree, &[symbol.index], span),
+            Scope::Function => self.emit(Opcode::CurrentClosure, &[], span),
+        }
+    }
+
+    /// Leaves the value of the last statement, or `()` for an empty block.
+    fn compile_block(&mut self, statements: &[ast::Statement], span: Span) -> Result<(), Diagnostic> {
+        if statements.is_empty() {
+            self.emit(Opcode::Unit, &[], span)?;
+        }
+        for (index, statement) in statements.iter().enumerate() {
+            self.compile_statement(statement, index + 1 == statements.len())?;
+        }
+        Ok(())
+    }
+
+    /// Leaves the value of `statement` if `keep` is set.
+    fn compile_statement(&mut self, statement: &ast::Statement, keep: bool) -> Result<(), Diagnostic> {
+        match &statement.kind {
+            ast::StatementKind::Expr(expression) => {
+                self.compile_expression(expression)?;
+                if !keep {
+                    self.emit(Opcode::Pop, &[], statement.span)?;
+                }
+            },
+            ast::StatementKind::Let{ name, value, .. } => {
+                match &value.kind {
+                    ast::ExpressionKind::Function{ params, body } => self.compile_function(Some(name), params, body, value.span)?,
+                    _ => self.compile_expression(value)?,
+                }
+                let symbol = self.symbols.define(name);
+                match symbol.scope {
+                    Scope::Global => self.emit(Opcode::SetGlobal, &[symbol.index], statement.span)?,
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index], statement.span)?,
+                };
+                if keep {
+                    self.emit(Opcode::Unit, &[], statement.span)?;
+                }
+            },
+            ast::StatementKind::Return(expression) => {
+                self.compile_expression(expression)?;
+                self.emit(Opcode::ReturnValue, &[], statement.span)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &ast::Expression) -> Result<(), Diagnostic> {
+        let span = expression.span;
+        match &expression.kind {
+            ast::ExpressionKind::Ident(name) => {
+                let symbol = self.symbols.resolve(name);
+                self.load_symbol(symbol, span)?;
+            },
+            ast::ExpressionKind::Int(value) => {
+                self.emit_constant(Object::Int(*value), span)?;
+            },
+            ast::ExpressionKind::Float(value) => {
+                self.emit_constant(Object::Float(*value), span)?;
+            },
+            ast::ExpressionKind::String(value) => {
+                self.emit_constant(Object::String(value.clone()), span)?;
+            },
+            ast::ExpressionKind::Boolean(true) => {
+                self.emit(Opcode::True, &[], span)?;
+            },
+            ast::ExpressionKind::Boolean(false) => {
+                self.emit(Opcode::False, &[], span)?;
+            },
+            ast::ExpressionKind::Unit => {
+                self.emit(Opcode::Unit, &[], span)?;
+            },
+            ast::ExpressionKind::Function{ params, body } => self.compile_function(None, params, body, span)?,
+            ast::ExpressionKind::MacroLiteral{ .. } => {
+                return Err(Diagnostic::error(String::from("macros must be defined by a top-level `let` statement"), span));
+            },
+            ast::ExpressionKind::PrefixExpression{ operator, right } => {
+                let op = match operator {
+                    ast::Operator::Prefix(ast::PrefixOperator::Minus) => Opcode::Minus,
+                    ast::Operator::Prefix(ast::PrefixOperator::Plus) => Opcode::Plus,
+                    ast::Operator::Prefix(ast::PrefixOperator::Bang) => Opcode::Bang,
+                    operator => return Err(Diagnostic::error(format!("unknown operator: {:?}", operator), span)),
+                };
+                self.compile_expression(right)?;
+                self.emit(op, &[], span)?;
+            },
+            ast::ExpressionKind::InfixExpression{ left, op, right } => {
+                let op = match op {
+                    ast::Operator::Infix(ast::InfixOperator::Plus) => Opcode::Add,
+                    ast::Operator::Infix(ast::InfixOperator::Minus) => Opcode::Sub,
+                    ast::Operator::Infix(ast::InfixOperator::Mul) => Opcode::Mul,
+                    ast::Operator::Infix(ast::InfixOperator::Div) => Opcode::Div,
+                    ast::Operator::Infix(ast::InfixOperator::Eq) => Opcode::Equal,
+                    ast::Operator::Infix(ast::InfixOperator::NotEq) => Opcode::NotEqual,
+                    ast::Operator::Infix(ast::InfixOperator::LT) => Opcode::LowerThan,
+                    ast::Operator::Infix(ast::InfixOperator::GT) => Opcode::GreaterThan,
+                    ast::Operator::Infix(ast::InfixOperator::LTE) => Opcode::LowerThanOrEqual,
+                    ast::Operator::Infix(ast::InfixOperator::GTE) => Opcode::GreaterThanOrEqual,
+                    op => return Err(Diagnostic::error(format!("unknown operator: {:?}", op), span)),
+                };
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                self.emit(op, &[], span)?;
+            },
+            ast::ExpressionKind::PostfixExpression{ op, .. } => {
+                return Err(Diagnostic::error(format!("unknown operator: {:?}", op), span));
+            },
+            ast::ExpressionKind::IfExpression{ condition, consequence, alternative } => {
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0], span)?;
+                self.compile_statement(consequence, true)?;
+                let jump = self.emit(Opcode::Jump, &[0], span)?;
+                self.patch_jump(jump_not_truthy, span)?;
+                self.compile_statement(alternative, true)?;
+                self.patch_jump(jump, span)?;
+            },
+            ast::ExpressionKind::CallExpression{ lambda, .. }
+                if matches!(&lambda.kind, ast::ExpressionKind::Ident(name) if name == "quote") => {
+                return Err(Diagnostic::error(String::from("`quote` is only supported by the evaluator"), span));
+            },
+            ast::ExpressionKind::CallExpression{ lambda, parameters } => {
+                self.compile_expression(lambda)?;
+                for parameter in parameters {
+                    self.compile_expression(parameter)?;
+                }
+                self.emit(Opcode::Call, &[parameters.len()], span)?;
+            },
+            ast::ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[elements.len()], span)?;
+            },
+            ast::ExpressionKind::HashLiteral(pairs) => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[pairs.len()], span)?;
+            },
+            ast::ExpressionKind::IndexExpression{ left, index } => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Opcode::Index, &[], span)?;
+            },
+            ast::ExpressionKind::BlockExpression(statements) => self.compile_block(statements, span)?,
+        }
+        Ok(())
+    }
+
+    /// Compiles the function to a constant, and emits the closure capturing
+    /// its free variables. `name` is the binding the function is defined
+    /// with, which its body can call itself through.
+    fn compile_function(&mut self, name: Option<&str>, params: &[ast::Identifier], body: &ast::Expression, span: Span) -> Result<(), Diagnostic> {
+        self.scopes.push(CompilationScope::default());
+        self.symbols = SymbolTable::new_enclosed(mem::take(&mut self.symbols));
+        if let Some(name) = name {
+            self.symbols.define_function_name(name);
+        }
+        for param in params {
+            self.symbols.define(param);
+        }
+        self.symbols.declare(body);
+
+        let compiled = self.compile_expression(body).and_then(|_| self.emit(Opcode::ReturnValue, &[], body.span));
+
+        let scope = self.scopes.pop().unwrap_or_default();
+        let outer = self.symbols.outer.take().expect("no enclosing symbol table");
+        let symbols = mem::replace(&mut self.symbols, *outer);
+        compiled?;
+
+        for symbol in &symbols.free {
+            match symbol.scope {
+                Scope::Local => self.emit(Opcode::CaptureLocal, &[symbol.index], span)?,
+                Scope::Free => self.emit(Opcode::CaptureFree, &[symbol.index], span)?,
+                _ => {
+                    self.load_symbol(*symbol, span)?;
+                    self.emit(Opcode::Capture, &[], span)?
+                },
+            };
+        }
+        self.constants.push(Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: scope.instructions,
+            params: params.to_vec(),
+            num_locals: symbols.num_definitions,
+            spans: scope.spans,
+        })));
+        self.emit(Opcode::Closure, &[self.constants.len() - 1, symbols.free.len()], span)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn compile_input(input: &str) -> Bytecode {
+        compile(&Parser::new(String::from(input)).parse_program().unwrap()).unwrap()
+    }
+
+    fn instructions(instructions: &[(Opcode, &[usize])]) -> String {
+        let instructions: Instructions = instructions
+            .iter()
+            .flat_map(|(op, operands)| code::make(*op, operands).unwrap())
+            .collect();
+        code::disassemble(&instructions)
+    }
+
+    fn function(constant: &Object) -> &CompiledFunction {
+        match constant {
+            Object::CompiledFunction(function) => function,
+            other => panic!("not a function: {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn test_compile_expressions() {
+        let bytecode = compile_input("1 + 2; -3.5");
+        assert_eq!(code::disassemble(&bytecode.main.instructions), instructions(&[
+            (Opcode::Constant, &[0]),
+            (Opcode::Constant, &[1]),
+            (Opcode::Add, &[]),
+            (Opcode::Pop, &[]),
+            (Opcode::Constant, &[2]),
+            (Opcode::Minus, &[]),
+        ]));
+        assert_eq!(bytecode.constants, vec![Object::Int(1), Object::Int(2), Object::Float(3.5)]);
+
+        let bytecode = compile_input("if (true) { 10 }; let x = [1, 2][0]; x");
+        assert_eq!(code::disassemble(&bytecode.main.instructions), instructions(&[
+            (Opcode::True, &[]),
+            (Opcode::JumpNotTruthy, &[10]),
+            (Opcode::Constant, &[0]),
+            (Opcode::Jump, &[11]),
+            (Opcode::Unit, &[]),
+            (Opcode::Pop, &[]),
+            (Opcode::Constant, &[1]),
+            (Opcode::Constant, &[2]),
+            (Opcode::Array, &[2]),
+            (Opcode::Constant, &[3]),
+            (Opcode::Index, &[]),
+            (Opcode::SetGlobal, &[0]),
+            (Opcode::GetGlobal, &[0]),
+        ]));
+        assert_eq!(bytecode.globals, vec!["x"]);
+
+        let bytecode = compile_input("let x = 1;");
+        assert_eq!(code::disassemble(&bytecode.main.instructions), instructions(&[
+            (Opcode::Constant, &[0]),
+            (Opcode::SetGlobal, &[0]),
+            (Opcode::Unit, &[]),
+        ]));
+        assert_eq!(code::disassemble(&compile_input("").main.instructions), instructions(&[(Opcode::Unit, &[])]));
+    }
+
+    #[test]
+    pub fn test_compile_functions() {
+        let bytecode = compile_input("let add = fn(a) { fn(b) { a + b + add } }; len");
+        assert_eq!(code::disassemble(&bytecode.main.instructions), instructions(&[
+            (Opcode::Closure, &[1, 0]),
+            (Opcode::SetGlobal, &[0]),
+            (Opcode::GetGlobal, &[1]),
+        ]));
+
+        let inner = function(&bytecode.constants[0]);
+        assert_eq!(code::disassemble(&inner.instructions), instructions(&[
+            (Opcode::GetFree, &[0]),
+            (Opcode::GetLocal, &[0]),
+            (Opcode::Add, &[]),
+            (Opcode::GetFree, &[1]),
+            (Opcode::Add, &[]),
+            (Opcode::ReturnValue, &[]),
+        ]));
+        assert_eq!(inner.params, vec!["b"]);
+
+        let outer = function(&bytecode.constants[1]);
+        assert_eq!(code::disassemble(&outer.instructions), instructions(&[
+            (Opcode::CaptureLocal, &[0]),
+            (Opcode::CurrentClosure, &[]),
+            (Opcode::Capture, &[]),
+            (Opcode::Closure, &[0, 2]),
+            (Opcode::ReturnValue, &[]),
+        ]));
+        assert_eq!(outer.num_locals, 1);
+    }
+
+    #[test]
+    pub fn test_symbol_table() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), Symbol { scope: Scope::Global, index: 0 });
+        assert_eq!(global.define("a"), Symbol { scope: Scope::Global, index: 0 });
+        assert_eq!(global.resolve("len"), Symbol { scope: Scope::Global, index: 1 });
+        assert_eq!(global.resolve("later"), Symbol { scope: Scope::Global, index: 2 });
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(local.define("b"), Symbol { scope: Scope::Local, index: 0 });
+        assert_eq!(local.define("b"), Symbol { scope: Scope::Local, index: 0 });
+        assert_eq!(local.define("d"), Symbol { scope: Scope::Local, index: 1 });
+        local.later.insert(String::from("e"));
+
+        let mut nested = SymbolTable::new_enclosed(local);
+        nested.define("c");
+        assert_eq!(nested.resolve("a"), Symbol { scope: Scope::Global, index: 0 });
+        assert_eq!(nested.resolve("b"), Symbol { scope: Scope::Free, index: 0 });
+        assert_eq!(nested.resolve("c"), Symbol { scope: Scope::Local, index: 0 });
+        assert_eq!(nested.resolve("e"), Symbol { scope: Scope::Free, index: 1 });
+        assert_eq!(nested.free, vec![Symbol { scope: Scope::Local, index: 0 }, Symbol { scope: Scope::Local, index: 2 }]);
+
+        let mut local = nested.outer.unwrap();
+        assert_eq!(local.resolve("e"), Symbol { scope: Scope::Global, index: 3 });
+        assert_eq!(local.define("e"), Symbol { scope: Scope::Local, index: 2 });
+        assert_eq!(local.outer.unwrap().global_names(), vec!["a", "len", "later", "e"]);
+    }
+}
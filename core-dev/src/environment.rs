@@ -0,0 +1,169 @@
+use std::cell::RefCell;
+use std::collections::{ HashMap, HashSet };
+use std::cmp::PartialEq;
+use std::fmt;
+use std::mem;
+use std::rc::{ Rc, Weak };
+
+use super::ast;
+use super::object::Object;
+
+/// An environment shared between the scope that created it and every closure
+/// capturing it.
+pub type Env = Rc<RefCell<Environment>>;
+
+/// The bindings of one scope, with a link to the scope it is nested in.
+///
+/// The parent is held strongly: a closure returned from a function must keep
+/// the call's scope alive after the call returns. A function bound in the
+/// environment it captured, like any named function, would then make a
+/// reference cycle, so it is held there with a `Weak` link instead, as v2's
+/// `ast::Context` holds its parent. The cycles left, like a closure bound in
+/// the scope enclosing the one it captured, are broken by `clear` when the
+/// interpreter is dropped.
+#[derive(Default)]
+pub struct Environment {
+    store: HashMap<ast::Identifier, Binding>,
+    outer: Option<Env>,
+}
+
+enum Binding {
+    Value(Object),
+    /// A function or macro capturing the environment holding it.
+    Own{ is_macro: bool, params: ast::Parameters, body: ast::Expression, env: Weak<RefCell<Environment>> },
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    /// A new scope whose lookups fall back to `outer`.
+    pub fn new_enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Self {
+            store: HashMap::new(),
+            outer: Some(Rc::clone(outer)),
+        }))
+    }
+
+    /// Looks `name` up in this scope, then in the enclosing ones.
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(Binding::Value(value)) => Some(value.clone()),
+            Some(Binding::Own{ is_macro, params, body, env }) => {
+                // This environment is alive, since it is being read.
+                let env = env.upgrade().expect("environment dropped while read");
+                let (params, body) = (params.clone(), body.clone());
+                Some(if *is_macro { Object::Macro{ params, body, env } } else { Object::Function{ params, body, env } })
+            },
+            None => self.outer.as_ref().and_then(|outer| outer.borrow().get(name))
+        }
+    }
+
+    /// Binds `name` in this scope, shadowing any binding of the enclosing ones.
+    pub fn set(&mut self, name: ast::Identifier, value: Object) {
+        let own = |env: &Env| std::ptr::eq(env.as_ptr(), self);
+        let binding = match value {
+            Object::Function{ params, body, env } if own(&env) => {
+                Binding::Own{ is_macro: false, params, body, env: Rc::downgrade(&env) }
+            },
+            Object::Macro{ params, body, env } if own(&env) => {
+                Binding::Own{ is_macro: true, params, body, env: Rc::downgrade(&env) }
+            },
+            value => Binding::Value(value)
+        };
+        self.store.insert(name, binding);
+    }
+
+    /// Removes the bindings of `env` and of every environment reachable from
+    /// them, freeing the values only reachable through reference cycles.
+    pub fn clear(env: &Env) {
+        let mut pending = vec![Rc::clone(env)];
+        let mut cleared = HashSet::new();
+        // The bindings are only dropped at the end, so that no environment is
+        // freed while being walked.
+        let mut bindings = Vec::new();
+        while let Some(env) = pending.pop() {
+            if !cleared.insert(Rc::as_ptr(&env)) {
+                continue;
+            }
+            let store = mem::take(&mut env.borrow_mut().store);
+            let mut values: Vec<&Object> = store.values().filter_map(|binding| match binding {
+                Binding::Value(value) => Some(value),
+                Binding::Own{ .. } => None,
+            }).collect();
+            while let Some(value) = values.pop() {
+                match value {
+                    Object::Function{ env, .. } | Object::Macro{ env, .. } => pending.push(Rc::clone(env)),
+                    Object::Array(elements) => values.extend(elements),
+                    Object::Hash(hash) => values.extend(hash.values()),
+                    Object::Return(value) => values.push(value),
+                    _ => ()
+                }
+            }
+            pending.extend(env.borrow().outer.iter().cloned());
+            bindings.push(store);
+        }
+    }
+}
+
+/// Environments are compared by identity: comparing their content would never
+/// end for a recursive function, which is stored in the environment it captured.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Only prints the names, for the same reason as `PartialEq`.
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<_> = self.store.keys().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &self.outer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_enclosed_environment() {
+        let global = Environment::new();
+        global.borrow_mut().set(String::from("x"), Object::Int(1));
+        global.borrow_mut().set(String::from("y"), Object::Int(2));
+
+        let local = Environment::new_enclosed(&global);
+        local.borrow_mut().set(String::from("x"), Object::Int(10));
+
+        assert_eq!(local.borrow().get("x"), Some(Object::Int(10)));
+        assert_eq!(local.borrow().get("y"), Some(Object::Int(2)));
+        assert_eq!(global.borrow().get("x"), Some(Object::Int(1)));
+        assert_eq!(local.borrow().get("z"), None);
+
+        global.borrow_mut().set(String::from("z"), Object::Int(3));
+        assert_eq!(local.borrow().get("z"), Some(Object::Int(3)));
+    }
+
+    #[test]
+    pub fn test_own_function() {
+        let global = Environment::new();
+        let local = Environment::new_enclosed(&global);
+        let function = Object::Function {
+            params: Vec::new(),
+            body: ast::Expression::from(ast::ExpressionKind::Unit),
+            env: Rc::clone(&local)
+        };
+        local.borrow_mut().set(String::from("f"), function.clone());
+        assert_eq!(local.borrow().get("f"), Some(function));
+
+        // The function does not keep alive the environment it is bound in.
+        let weak = Rc::downgrade(&local);
+        drop(local);
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,825 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use super::ast;
+use super::builtins;
+use super::object::{ HashKey, Object, RuntimeError };
+use super::environment::{ Env, Environment };
+use core_stable::span::Span;
+
+macro_rules! error {
+    ( $($arg:tt)* ) => {
+        Object::Error(RuntimeError::from(format!($($arg)*)))
+    };
+}
+
+/// The deepest the calls can nest before the evaluator gives up.
+pub const MAX_DEPTH: usize = 10_000;
+
+/// The native stack left when a call grows it, and by how much. A call of
+/// the evaluator takes a few kilobytes of stack, tens in a debug build.
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_GROWTH: usize = 8 * 1024 * 1024;
+
+thread_local! {
+    /// The number of calls being evaluated.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn eval_program(program: &ast::Program, env: &Env) -> Object {
+    match eval_block(&program.global, env) {
+        Object::Return(value) => *value,
+        result => result
+    }
+}
+
+/// Evaluates the statements one after the other, the value of a block being
+/// the value of its last statement. `return`s and errors are not unwrapped so
+/// that they can go through every enclosing block.
+fn eval_block(statements: &[ast::Statement], env: &Env) -> Object {
+    let mut result = Object::Unit;
+    for statement in statements {
+        result = eval_statement(statement, env);
+        if let Object::Return(_) | Object::Error(_) = result {
+            break;
+        }
+    }
+    result
+}
+
+pub fn eval_statement(statement: &ast::Statement, env: &Env) -> Object {
+    match &statement.kind {
+        ast::StatementKind::Expr(expr) => eval_expression(expr, env),
+        ast::StatementKind::Let{ name, value, .. } => {
+            let value = eval_expression(value, env);
+            if value.is_return_or_error() {
+                return value;
+            }
+            env.borrow_mut().set(name.clone(), value);
+            Object::Unit
+        },
+        ast::StatementKind::Return(expr) => {
+            let value = eval_expression(expr, env);
+            if value.is_return_or_error() {
+                return value;
+            }
+            Object::Return(Box::new(value))
+        }
+    }
+}
+
+/// Evaluates `expression`, pointing the errors it raises at its span. The
+/// errors raised by a subexpression already point at the subexpression.
+pub fn eval_expression(expression: &ast::Expression, env: &Env) -> Object {
+    match eval_expression_kind(expression, env) {
+        Object::Error(RuntimeError { message, span: None }) => Object::Error(RuntimeError {
+            message,
+            span: Some(expression.span)
+        }),
+        result => result
+    }
+}
+
+fn eval_expression_kind(expression: &ast::Expression, env: &Env) -> Object {
+    match &expression.kind {
+        ast::ExpressionKind::Ident(ident) => match env.borrow().get(ident) {
+            Some(value) => value,
+            None => match builtins::lookup(ident) {
+                Some(builtin) => Object::Builtin(builtin),
+                None => error!("identifier not found: {}", ident)
+            }
+        },
+        ast::ExpressionKind::Int(value) => Object::Int(*value),
+        ast::ExpressionKind::Float(value) => Object::Float(*value),
+        ast::ExpressionKind::String(value) => Object::String(value.clone()),
+        ast::ExpressionKind::Boolean(value) => Object::Boolean(*value),
+        ast::ExpressionKind::Unit => Object::Unit,
+        ast::ExpressionKind::Function{ params, body } => Object::Function {
+            params: params.clone(),
+            body: (**body).clone(),
+            env: Rc::clone(env)
+        },
+        ast::ExpressionKind::MacroLiteral{ .. } => error!("macros must be defined by a top-level `let` statement"),
+        ast::ExpressionKind::PrefixExpression{ operator, right } => {
+            let right = eval_expression(right, env);
+            if right.is_return_or_error() {
+                return right;
+            }
+            eval_prefix_expression(operator, right)
+        },
+        ast::ExpressionKind::InfixExpression{ left, op, right } => {
+            let left = eval_expression(left, env);
+            if left.is_return_or_error() {
+                return left;
+            }
+            let right = eval_expression(right, env);
+            if right.is_return_or_error() {
+                return right;
+            }
+            eval_infix_expression(op, left, right)
+        },
+        ast::ExpressionKind::PostfixExpression{ op, .. } => error!("unknown operator: {:?}", op),
+        ast::ExpressionKind::IfExpression{ condition, consequence, alternative } => {
+            let condition = eval_expression(condition, env);
+            if condition.is_return_or_error() {
+                condition
+            } else if condition.is_truthy() {
+                eval_statement(consequence, env)
+            } else {
+                eval_statement(alternative, env)
+            }
+        },
+        ast::ExpressionKind::CallExpression{ lambda, parameters } if is_ident(lambda, "quote") => quote(parameters, env),
+        ast::ExpressionKind::CallExpression{ lambda, parameters } => {
+            let function = eval_expression(lambda, env);
+            if function.is_return_or_error() {
+                return function;
+            }
+            match eval_expressions(parameters, env) {
+                Ok(args) => apply_function(function, args),
+                Err(err) => err
+            }
+        },
+        ast::ExpressionKind::ArrayLiteral(elements) => match eval_expressions(elements, env) {
+            Ok(elements) => Object::Array(elements),
+            Err(err) => err
+        },
+        ast::ExpressionKind::HashLiteral(pairs) => {
+            let mut hash = BTreeMap::new();
+            for (key, value) in pairs {
+                let key_value = eval_expression(key, env);
+                if key_value.is_return_or_error() {
+                    return key_value;
+                }
+                let key = match HashKey::from_object(&key_value) {
+                    Some(hash_key) => hash_key,
+                    None => return Object::Error(RuntimeError {
+                        message: format!("unusable as hash key: {}", key_value.type_name()),
+                        span: Some(key.span)
+                    })
+                };
+                let value = eval_expression(value, env);
+                if value.is_return_or_error() {
+                    return value;
+                }
+                hash.insert(key, value);
+            }
+            Object::Hash(hash)
+        },
+        ast::ExpressionKind::IndexExpression{ left, index } => {
+            let left = eval_expression(left, env);
+            if left.is_return_or_error() {
+                return left;
+            }
+            let index = eval_expression(index, env);
+            if index.is_return_or_error() {
+                return index;
+            }
+            eval_index_expression(left, index)
+        },
+        ast::ExpressionKind::BlockExpression(statements) => eval_block(statements, env),
+    }
+}
+
+/// Evaluates `expressions` from left to right, stopping on the first error
+/// or `return`.
+fn eval_expressions(expressions: &[ast::Expression], env: &Env) -> Result<Vec<Object>, Object> {
+    let mut values = Vec::with_capacity(expressions.len());
+    for expression in expressions {
+        let value = eval_expression(expression, env);
+        if value.is_return_or_error() {
+            return Err(value);
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+pub fn eval_prefix_expression(operator: &ast::Operator, right: Object) -> Object {
+    match (operator, right) {
+        (ast::Operator::Prefix(ast::PrefixOperator::Bang), right) => Object::Boolean(!right.is_truthy()),
+        (ast::Operator::Prefix(ast::PrefixOperator::Minus), Object::Int(value)) => match value.checked_neg() {
+            Some(value) => Object::Int(value),
+            None => error!("integer overflow: -{}", value)
+        },
+        (ast::Operator::Prefix(ast::PrefixOperator::Minus), Object::Float(value)) => Object::Float(-value),
+        (ast::Operator::Prefix(ast::PrefixOperator::Plus), Object::Int(value)) => Object::Int(value),
+        (ast::Operator::Prefix(ast::PrefixOperator::Plus), Object::Float(value)) => Object::Float(value),
+        (ast::Operator::Prefix(ast::PrefixOperator::Minus), right) => error!("unknown operator: -{}", right.type_name()),
+        (ast::Operator::Prefix(ast::PrefixOperator::Plus), right) => error!("unknown operator: +{}", right.type_name()),
+        (operator, right) => error!("unknown operator: {:?} {}", operator, right.type_name())
+    }
+}
+
+/// Integers are promoted to floats when mixed with floats, so that `1 + 0.5`
+/// is `1.5` and `1 == 1.0` is `true`.
+pub fn eval_infix_expression(op: &ast::Operator, left: Object, right: Object) -> Object {
+    let op = match op {
+        ast::Operator::Infix(op) => op,
+        op => return error!("unknown operator: {} {:?} {}", left.type_name(), op, right.type_name())
+    };
+    match (left, right) {
+        (Object::Int(left), Object::Int(right)) => eval_integer_infix_expression(op, left, right),
+        (Object::Float(left), Object::Float(right)) => eval_float_infix_expression(op, left, right),
+        (Object::Int(left), Object::Float(right)) => eval_float_infix_expression(op, left as f64, right),
+        (Object::Float(left), Object::Int(right)) => eval_float_infix_expression(op, left, right as f64),
+        (Object::String(left), Object::String(right)) => eval_string_infix_expression(op, left, right),
+        (left, right) if left.type_name() != right.type_name() => {
+            error!("type mismatch: {} {} {}", left.type_name(), infix_symbol(op), right.type_name())
+        },
+        (left, right) => match op {
+            ast::InfixOperator::Eq => Object::Boolean(left == right),
+            ast::InfixOperator::NotEq => Object::Boolean(left != right),
+            op => error!("unknown operator: {} {} {}", left.type_name(), infix_symbol(op), right.type_name())
+        }
+    }
+}
+
+fn eval_integer_infix_expression(op: &ast::InfixOperator, left: isize, right: isize) -> Object {
+    let checked = |result: Option<isize>| match result {
+        Some(value) => Object::Int(value),
+        None if right == 0 && *op == ast::InfixOperator::Div => error!("division by zero: {} / {}", left, right),
+        None => error!("integer overflow: {} {} {}", left, infix_symbol(op), right)
+    };
+    match op {
+        ast::InfixOperator::Plus => checked(left.checked_add(right)),
+        ast::InfixOperator::Minus => checked(left.checked_sub(right)),
+        ast::InfixOperator::Mul => checked(left.checked_mul(right)),
+        ast::InfixOperator::Div => checked(left.checked_div(right)),
+        ast::InfixOperator::Eq => Object::Boolean(left == right),
+        ast::InfixOperator::NotEq => Object::Boolean(left != right),
+        ast::InfixOperator::LT => Object::Boolean(left < right),
+        ast::InfixOperator::GT => Object::Boolean(left > right),
+        ast::InfixOperator::LTE => Object::Boolean(left <= right),
+        ast::InfixOperator::GTE => Object::Boolean(left >= right),
+        ast::InfixOperator::Personalised(op) => error!("unknown operator: INTEGER {} INTEGER", op)
+    }
+}
+
+/// Follows IEEE 754: dividing by zero gives an infinity or NaN, not an error.
+fn eval_float_infix_expression(op: &ast::InfixOperator, left: f64, right: f64) -> Object {
+    match op {
+        ast::InfixOperator::Plus => Object::Float(left + right),
+        ast::InfixOperator::Minus => Object::Float(left - right),
+        ast::InfixOperator::Mul => Object::Float(left * right),
+        ast::InfixOperator::Div => Object::Float(left / right),
+        ast::InfixOperator::Eq => Object::Boolean(left == right),
+        ast::InfixOperator::NotEq => Object::Boolean(left != right),
+        ast::InfixOperator::LT => Object::Boolean(left < right),
+        ast::InfixOperator::GT => Object::Boolean(left > right),
+        ast::InfixOperator::LTE => Object::Boolean(left <= right),
+        ast::InfixOperator::GTE => Object::Boolean(left >= right),
+        op => error!("unknown operator: FLOAT {} FLOAT", infix_symbol(op))
+    }
+}
+
+fn eval_string_infix_expression(op: &ast::InfixOperator, left: String, right: String) -> Object {
+    match op {
+        ast::InfixOperator::Plus => Object::String(left + &right),
+        ast::InfixOperator::Eq => Object::Boolean(left == right),
+        ast::InfixOperator::NotEq => Object::Boolean(left != right),
+        op => error!("unknown operator: STRING {} STRING", infix_symbol(op))
+    }
+}
+
+fn infix_symbol(op: &ast::InfixOperator) -> &str {
+    match op {
+        ast::InfixOperator::Plus => "+",
+        ast::InfixOperator::Minus => "-",
+        ast::InfixOperator::Mul => "*",
+        ast::InfixOperator::Div => "/",
+        ast::InfixOperator::Eq => "==",
+        ast::InfixOperator::NotEq => "!=",
+        ast::InfixOperator::GT => ">",
+        ast::InfixOperator::LT => "<",
+        ast::InfixOperator::GTE => ">=",
+        ast::InfixOperator::LTE => "<=",
+        ast::InfixOperator::Personalised(op) => op
+    }
+}
+
+/// Negative indices count from the end: `a[-1]` is the last element of `a`.
+/// Looking up a missing key of a hash gives `()`.
+pub fn eval_index_expression(left: Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Int(index)) => {
+            let position = if index < 0 { elements.len() as isize + index } else { index };
+            match elements.get(position as usize) {
+                Some(element) if position >= 0 => element.clone(),
+                _ => error!("index out of bounds: the length is {} but the index is {}", elements.len(), index)
+            }
+        },
+        (Object::Hash(pairs), index) => match HashKey::from_object(&index) {
+            Some(key) => pairs.get(&key).cloned().unwrap_or(Object::Unit),
+            None => error!("unusable as hash key: {}", index.type_name())
+        },
+        (left, index) => error!("index operator not supported: {}[{}]", left.type_name(), index.type_name())
+    }
+}
+
+fn is_ident(expression: &ast::Expression, name: &str) -> bool {
+    matches!(&expression.kind, ast::ExpressionKind::Ident(ident) if ident == name)
+}
+
+/// `quote(expression)` gives `expression` unevaluated, except for the
+/// `unquote(...)` calls in it which are replaced by the value of their
+/// argument.
+fn quote(args: &[ast::Expression], env: &Env) -> Object {
+    let expression = match args {
+        [expression] => expression.clone(),
+        _ => return error!("wrong number of arguments to `quote`: expected 1, got {}", args.len())
+    };
+    let mut err = None;
+    let expression = expression.modify(&mut |expression| match &expression.kind {
+        ast::ExpressionKind::CallExpression{ lambda, parameters } if err.is_none() && is_ident(lambda, "unquote") => {
+            match unquote(parameters, env, expression.span) {
+                Ok(unquoted) => unquoted,
+                Err(unquote_err) => {
+                    err = Some(unquote_err);
+                    expression
+                }
+            }
+        },
+        _ => expression
+    });
+    err.unwrap_or(Object::Quote(expression))
+}
+
+fn unquote(args: &[ast::Expression], env: &Env, span: Span) -> Result<ast::Expression, Object> {
+    let value = match args {
+        [arg] => eval_expression(arg, env),
+        _ => return Err(error!("wrong number of arguments to `unquote`: expected 1, got {}", args.len()))
+    };
+    if value.is_return_or_error() {
+        return Err(value);
+    }
+    let type_name = value.type_name();
+    object_to_expression(value, span).ok_or_else(|| Object::Error(RuntimeError {
+        message: format!("cannot unquote {}", type_name),
+        span: Some(span)
+    }))
+}
+
+/// The expression evaluating to `object`, if it can be written as one.
+fn object_to_expression(object: Object, span: Span) -> Option<ast::Expression> {
+    let kind = match object {
+        Object::Int(value) => ast::ExpressionKind::Int(value),
+        Object::Float(value) => ast::ExpressionKind::Float(value),
+        Object::String(value) => ast::ExpressionKind::String(value),
+        Object::Boolean(value) => ast::ExpressionKind::Boolean(value),
+        Object::Unit => ast::ExpressionKind::Unit,
+        Object::Quote(expression) => return Some(expression),
+        Object::Array(elements) => ast::ExpressionKind::ArrayLiteral(
+            elements.into_iter().map(|element| object_to_expression(element, span)).collect::<Option<_>>()?
+        ),
+        Object::Hash(pairs) => ast::ExpressionKind::HashLiteral(
+            pairs
+                .into_iter()
+                .map(|(key, value)| Some((object_to_expression(key.into(), span)?, object_to_expression(value, span)?)))
+                .collect::<Option<_>>()?
+        ),
+        _ => return None
+    };
+    Some(ast::Node::new(kind, span))
+}
+
+/// Calls `function`, a Monkey function or a builtin, with `args`.
+pub fn apply_function(function: Object, args: Vec<Object>) -> Object {
+    match function {
+        Object::Function{ params, body, env } => {
+            if params.len() != args.len() {
+                return error!("wrong number of arguments: expected {}, got {}", params.len(), args.len());
+            }
+            let depth = DEPTH.with(Cell::get);
+            if depth >= MAX_DEPTH {
+                return error!("stack overflow");
+            }
+            let env = Environment::new_enclosed(&env);
+            for (param, arg) in params.into_iter().zip(args) {
+                env.borrow_mut().set(param, arg);
+            }
+            DEPTH.with(|cell| cell.set(depth + 1));
+            let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || eval_expression(&body, &env));
+            DEPTH.with(|cell| cell.set(depth));
+            match result {
+                Object::Return(value) => *value,
+                result => result
+            }
+        },
+        Object::Builtin(builtin) => {
+            if !builtin.arity.accepts(args.len()) {
+                return error!("wrong number of arguments to `{}`: expected {}, got {}", builtin.name, builtin.arity, args.len());
+            }
+            (builtin.func)(args)
+        },
+        function => error!("not a function: {}", function.type_name())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    struct TestEval {
+        input: String,
+        expected: Object,
+    }
+
+    fn test_eval(input: &str) -> Object {
+        let program = Parser::new(String::from(input)).parse_program().unwrap();
+        eval_program(&program, &Environment::new())
+    }
+
+    fn run(tests: &[TestEval]) {
+        for test in tests.iter() {
+            let evaluated = test_eval(&test.input);
+            println!("Input: {}\nExpected: {:?}\nGot: {:?}\n\n", test.input, test.expected, evaluated);
+            assert_eq!(evaluated, test.expected);
+        }
+    }
+
+    #[test]
+    pub fn test_eval_integer_expression() {
+        run(&[
+            TestEval { input: String::from("5"), expected: Object::Int(5) },
+            TestEval { input: String::from("10"), expected: Object::Int(10) },
+            TestEval { input: String::from("-5"), expected: Object::Int(-5) },
+            TestEval { input: String::from("--10"), expected: Object::Int(10) },
+            TestEval { input: String::from("5 + 5 + 5 + 5 - 10"), expected: Object::Int(10) },
+            TestEval { input: String::from("2 * 2 * 2 * 2 * 2"), expected: Object::Int(32) },
+            TestEval { input: String::from("-50 + 100 + -50"), expected: Object::Int(0) },
+            TestEval { input: String::from("5 * 2 + 10"), expected: Object::Int(20) },
+            TestEval { input: String::from("5 + 2 * 10"), expected: Object::Int(25) },
+            TestEval { input: String::from("50 / 2 * 2 + 10"), expected: Object::Int(60) },
+            TestEval { input: String::from("2 * (5 + 10)"), expected: Object::Int(30) },
+            TestEval { input: String::from("(5 + 10 * 2 + 15 / 3) * 2 + -10"), expected: Object::Int(50) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_eval_boolean_expression() {
+        run(&[
+            TestEval { input: String::from("true"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("false"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("1 < 2"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("2 <= 1"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("2 > 1"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("1 > 2"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("1 == 1"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("1 != 1"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("true == true"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("true != false"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("(1 < 2) == true"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("(2 < 1) == true"), expected: Object::Boolean(false) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_bang_operator() {
+        run(&[
+            TestEval { input: String::from("!true"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("!false"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("!5"), expected: Object::Boolean(false) },
+            TestEval { input: String::from("!!true"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("!!5"), expected: Object::Boolean(true) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_if_else_expressions() {
+        run(&[
+            TestEval { input: String::from("if (true) { 10 }"), expected: Object::Int(10) },
+            TestEval { input: String::from("if (false) { 10 }"), expected: Object::Unit },
+            TestEval { input: String::from("if (1) { 10 }"), expected: Object::Int(10) },
+            TestEval { input: String::from("if (1 < 2) { 10 }"), expected: Object::Int(10) },
+            TestEval { input: String::from("if (2 < 1) { 10 }"), expected: Object::Unit },
+            TestEval { input: String::from("if (2 < 1) { 10 } else { 20 }"), expected: Object::Int(20) },
+            TestEval { input: String::from("if (1 < 2) { 10 } else { 20 }"), expected: Object::Int(10) },
+            TestEval { input: String::from("if (2 < 1) { 10 } else if (1 < 2) { 30 } else { 20 }"), expected: Object::Int(30) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_return_statements() {
+        run(&[
+            TestEval { input: String::from("return 10;"), expected: Object::Int(10) },
+            TestEval { input: String::from("return 10; 9;"), expected: Object::Int(10) },
+            TestEval { input: String::from("return 2 * 5; 9;"), expected: Object::Int(10) },
+            TestEval { input: String::from("9; return 2 * 5; 9;"), expected: Object::Int(10) },
+            TestEval {
+                input: String::from("if (10 < 1) { 1 } else { if (1 < 10) { return 10; } return 1; }"),
+                expected: Object::Int(10)
+            },
+            TestEval { input: String::from("let v = { return 5; 6 }; v + 1"), expected: Object::Int(5) },
+            TestEval { input: String::from("let f = fn() { let v = { return 5; 6 }; 9 }; f()"), expected: Object::Int(5) },
+            TestEval { input: String::from("let f = fn(x) { x }; let g = fn() { f({ return 3; }); 4 }; g()"), expected: Object::Int(3) },
+            TestEval { input: String::from("let f = fn() { [1, { return 2; }, 3] }; f()"), expected: Object::Int(2) },
+            TestEval { input: String::from("let f = fn() { puts({ return 3; }); 4 }; f()"), expected: Object::Int(3) },
+            TestEval { input: String::from("let f = fn() { { return 4; } + 1 }; f() * 2"), expected: Object::Int(8) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_error_handling() {
+        run(&[
+            TestEval { input: String::from("5 + true;"), expected: Object::Error(RuntimeError::from("type mismatch: INTEGER + BOOLEAN")) },
+            TestEval { input: String::from("5 + true; 5;"), expected: Object::Error(RuntimeError::from("type mismatch: INTEGER + BOOLEAN")) },
+            TestEval { input: String::from("-true"), expected: Object::Error(RuntimeError::from("unknown operator: -BOOLEAN")) },
+            TestEval { input: String::from("true + false;"), expected: Object::Error(RuntimeError::from("unknown operator: BOOLEAN + BOOLEAN")) },
+            TestEval { input: String::from("5; true + false; 5"), expected: Object::Error(RuntimeError::from("unknown operator: BOOLEAN + BOOLEAN")) },
+            TestEval { input: String::from("if (10 < 1) { 1 } else { true + false; }"), expected: Object::Error(RuntimeError::from("unknown operator: BOOLEAN + BOOLEAN")) },
+            TestEval { input: String::from("foobar"), expected: Object::Error(RuntimeError::from("identifier not found: foobar")) },
+            TestEval { input: String::from("10 / 0"), expected: Object::Error(RuntimeError::from("division by zero: 10 / 0")) },
+            TestEval { input: String::from("5(1)"), expected: Object::Error(RuntimeError::from("not a function: INTEGER")) },
+            TestEval { input: String::from("fn(x) { x }(1, 2)"), expected: Object::Error(RuntimeError::from("wrong number of arguments: expected 1, got 2")) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_error_spans() {
+        let tests = [
+            ("let x = 1;\nlet y = x + true;", Span::new(19, 8, 2, 9)),
+            ("let f = fn() { 1 + foo };\nf()", Span::new(19, 3, 1, 20)),
+            ("let f = fn(x) { x };\nf(1, 2)", Span::new(21, 7, 2, 1)),
+        ];
+
+        for (input, span) in tests.iter() {
+            match test_eval(input) {
+                Object::Error(err) => assert_eq!(err.span, Some(*span), "{}", input),
+                other => panic!("{}: {:?}", input, other)
+            }
+        }
+    }
+
+    fn test_eval_infix(left: ast::ExpressionKind, op: ast::InfixOperator, right: ast::ExpressionKind) -> Object {
+        let expression = ast::Expression::from(ast::ExpressionKind::InfixExpression {
+            left: Box::new(left.into()),
+            op: ast::Operator::Infix(op),
+            right: Box::new(right.into())
+        });
+        eval_program(&ast::Program::from(vec![expression.into()]), &Environment::new())
+    }
+
+    #[test]
+    pub fn test_eval_float_expression() {
+        let float = ast::ExpressionKind::Float;
+        assert_eq!(test_eval_infix(float(1.5), ast::InfixOperator::Plus, float(2.0)), Object::Float(3.5));
+        assert_eq!(test_eval_infix(float(1.5), ast::InfixOperator::Mul, float(2.0)), Object::Float(3.0));
+        assert_eq!(test_eval_infix(float(1.0), ast::InfixOperator::Div, float(4.0)), Object::Float(0.25));
+        assert_eq!(test_eval_infix(float(1.0), ast::InfixOperator::LT, float(4.0)), Object::Boolean(true));
+        assert_eq!(
+            test_eval_infix(float(1.0), ast::InfixOperator::Plus, ast::ExpressionKind::Boolean(true)),
+            Object::Error(RuntimeError::from("type mismatch: FLOAT + BOOLEAN"))
+        );
+    }
+
+    #[test]
+    pub fn test_eval_array_expression() {
+        run(&[
+            TestEval {
+                input: String::from("[1, 2 * 2, 3 + 3]"),
+                expected: Object::Array(vec![Object::Int(1), Object::Int(4), Object::Int(6)])
+            },
+            TestEval { input: String::from("[]"), expected: Object::Array(vec![]) },
+            TestEval { input: String::from("[1, 2, 3][0]"), expected: Object::Int(1) },
+            TestEval { input: String::from("[1, 2, 3][1 + 1]"), expected: Object::Int(3) },
+            TestEval { input: String::from("let i = 0; [1][i]"), expected: Object::Int(1) },
+            TestEval { input: String::from("let a = [1, 2, 3]; a[0] + a[1] + a[2]"), expected: Object::Int(6) },
+            TestEval { input: String::from("[1, 2, 3][-1]"), expected: Object::Int(3) },
+            TestEval { input: String::from("[1, 2, 3][-3]"), expected: Object::Int(1) },
+            TestEval { input: String::from("[[1, 2], [3]][0][1]"), expected: Object::Int(2) },
+            TestEval { input: String::from("let first = fn(a) { a[0] }; first([fn(x) { x * 2 }])(4)"), expected: Object::Int(8) },
+            TestEval { input: String::from("[1, 2] == [1, 2]"), expected: Object::Boolean(true) },
+            TestEval {
+                input: String::from("[1, 2, 3][3]"),
+                expected: Object::Error(RuntimeError::from("index out of bounds: the length is 3 but the index is 3"))
+            },
+            TestEval {
+                input: String::from("[1, 2, 3][-4]"),
+                expected: Object::Error(RuntimeError::from("index out of bounds: the length is 3 but the index is -4"))
+            },
+            TestEval {
+                input: String::from("[1][true]"),
+                expected: Object::Error(RuntimeError::from("index operator not supported: ARRAY[BOOLEAN]"))
+            },
+            TestEval {
+                input: String::from("1[0]"),
+                expected: Object::Error(RuntimeError::from("index operator not supported: INTEGER[INTEGER]"))
+            },
+            TestEval { input: String::from("[1, foo]"), expected: Object::Error(RuntimeError::from("identifier not found: foo")) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_eval_hash_expression() {
+        let input = r#"
+            let two = "two";
+            {
+                "one": 10 - 9,
+                two: 1 + 1,
+                "thr" + "ee": 6 / 2,
+                4: 4,
+                true: 5,
+                false: 6
+            }
+        "#;
+        let expected: BTreeMap<HashKey, Object> = vec![
+            (HashKey::String(String::from("one")), Object::Int(1)),
+            (HashKey::String(String::from("two")), Object::Int(2)),
+            (HashKey::String(String::from("three")), Object::Int(3)),
+            (HashKey::Int(4), Object::Int(4)),
+            (HashKey::Boolean(true), Object::Int(5)),
+            (HashKey::Boolean(false), Object::Int(6)),
+        ].into_iter().collect();
+        let result = test_eval(input);
+        assert_eq!(result, Object::Hash(expected));
+        assert_eq!(result.inspect(), r#"{false: 6, true: 5, 4: 4, "one": 1, "three": 3, "two": 2}"#);
+
+        run(&[
+            TestEval { input: String::from(r#"{"foo": 5}["foo"]"#), expected: Object::Int(5) },
+            TestEval { input: String::from(r#"{"foo": 5}["bar"]"#), expected: Object::Unit },
+            TestEval { input: String::from(r#"let key = "foo"; {"foo": 5}[key]"#), expected: Object::Int(5) },
+            TestEval { input: String::from(r#"{}["foo"]"#), expected: Object::Unit },
+            TestEval { input: String::from("{5: 5}[5]"), expected: Object::Int(5) },
+            TestEval { input: String::from("{true: 5}[true]"), expected: Object::Int(5) },
+            TestEval { input: String::from("{1: 1, 1: 2}[1]"), expected: Object::Int(2) },
+            TestEval { input: String::from(r#"{"a": [1, 2]}["a"][-1]"#), expected: Object::Int(2) },
+            TestEval { input: String::from("{1: 2, 3: 4} == {3: 4, 1: 2}"), expected: Object::Boolean(true) },
+            TestEval {
+                input: String::from(r#"{"name": "Monkey"}[fn(x) { x }];"#),
+                expected: Object::Error(RuntimeError::from("unusable as hash key: FUNCTION"))
+            },
+            TestEval {
+                input: String::from("{[1]: 2}"),
+                expected: Object::Error(RuntimeError::from("unusable as hash key: ARRAY"))
+            },
+            TestEval {
+                input: String::from("{1.5: 2}"),
+                expected: Object::Error(RuntimeError::from("unusable as hash key: FLOAT"))
+            },
+        ]);
+
+        match test_eval("let h = {\n  [1]: 2\n};") {
+            Object::Error(err) => assert_eq!(err.span, Some(Span::new(12, 3, 2, 3))),
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    pub fn test_eval_mixed_number_expression() {
+        run(&[
+            TestEval { input: String::from("2.5E3 + 1_000"), expected: Object::Float(3500.0) },
+            TestEval { input: String::from("1 + 0.5"), expected: Object::Float(1.5) },
+            TestEval { input: String::from("0.5 * 4"), expected: Object::Float(2.0) },
+            TestEval { input: String::from("7 / 2"), expected: Object::Int(3) },
+            TestEval { input: String::from("7 / 2.0"), expected: Object::Float(3.5) },
+            TestEval { input: String::from("1 == 1.0"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("1e-9 < 1"), expected: Object::Boolean(true) },
+            TestEval { input: String::from("-1.5 + 1"), expected: Object::Float(-0.5) },
+            TestEval { input: String::from("1 / 0.0"), expected: Object::Float(f64::INFINITY) },
+            TestEval { input: String::from("1.5 + true"), expected: Object::Error(RuntimeError::from("type mismatch: FLOAT + BOOLEAN")) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_eval_string_expression() {
+        let string = |value: &str| ast::ExpressionKind::String(String::from(value));
+        assert_eq!(
+            test_eval_infix(string("Hello"), ast::InfixOperator::Plus, string(" World!")),
+            Object::String(String::from("Hello World!"))
+        );
+        assert_eq!(test_eval_infix(string("a"), ast::InfixOperator::Eq, string("a")), Object::Boolean(true));
+        assert_eq!(test_eval_infix(string("a"), ast::InfixOperator::NotEq, string("a")), Object::Boolean(false));
+        assert_eq!(
+            test_eval_infix(string("a"), ast::InfixOperator::Minus, string("a")),
+            Object::Error(RuntimeError::from("unknown operator: STRING - STRING"))
+        );
+        assert_eq!(
+            test_eval(r#"let greet = fn(name) { "Hello, \"" + name + "\"\n" }; greet("w\u{f6}rld")"#),
+            Object::String(String::from("Hello, \"wörld\"\n"))
+        );
+    }
+
+    #[test]
+    pub fn test_let_statements() {
+        run(&[
+            TestEval { input: String::from("let a = 5; a;"), expected: Object::Int(5) },
+            TestEval { input: String::from("let a = 5 * 5; a;"), expected: Object::Int(25) },
+            TestEval { input: String::from("let a = 5; let b = a; b;"), expected: Object::Int(5) },
+            TestEval { input: String::from("let a = 5; let b = a; let c = a + b + 5; c;"), expected: Object::Int(15) },
+            TestEval { input: String::from("let snake_case = 5; let _ = snake_case * 2; _;"), expected: Object::Int(10) },
+            TestEval { input: String::from("let caf\u{e9} = 5; caf\u{65}\u{301};"), expected: Object::Int(5) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_function_object() {
+        match test_eval("fn(x) { x + 2; };") {
+            Object::Function{ params, body, .. } => {
+                assert_eq!(params, vec![String::from("x")]);
+                assert_eq!(body, ast::ExpressionKind::BlockExpression(vec![
+                    ast::StatementKind::Expr(ast::ExpressionKind::InfixExpression {
+                        left: Box::new(ast::ExpressionKind::Ident(String::from("x")).into()),
+                        op: ast::Operator::Infix(ast::InfixOperator::Plus),
+                        right: Box::new(ast::ExpressionKind::Int(2).into())
+                    }.into()).into()
+                ]).into());
+            },
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    pub fn test_function_application() {
+        run(&[
+            TestEval { input: String::from("let identity = fn(x) { x; }; identity(5);"), expected: Object::Int(5) },
+            TestEval { input: String::from("let identity = fn(x) { return x; }; identity(5);"), expected: Object::Int(5) },
+            TestEval { input: String::from("let double = fn(x) { x * 2; }; double(5);"), expected: Object::Int(10) },
+            TestEval { input: String::from("let add = fn(x, y) { x + y; }; add(5, 5);"), expected: Object::Int(10) },
+            TestEval { input: String::from("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));"), expected: Object::Int(20) },
+            TestEval { input: String::from("fn(x) { x; }(5)"), expected: Object::Int(5) },
+            TestEval { input: String::from("puts(1)"), expected: Object::Unit },
+        ]);
+    }
+
+    #[test]
+    pub fn test_builtin_application() {
+        fn answer(args: Vec<Object>) -> Object {
+            Object::Int(42 + args.len() as isize)
+        }
+        let env = Environment::new();
+        env.borrow_mut().set(String::from("answer"), Object::Builtin(crate::object::Builtin::new(
+            "answer",
+            crate::object::Arity::Between(1, 2),
+            answer
+        )));
+        let program = Parser::new(String::from("answer(1, 2)")).parse_program().unwrap();
+        assert_eq!(eval_program(&program, &env), Object::Int(44));
+        let program = Parser::new(String::from("answer()")).parse_program().unwrap();
+        assert_eq!(
+            eval_program(&program, &env),
+            Object::Error(RuntimeError::from("wrong number of arguments to `answer`: expected 1 or 2, got 0"))
+        );
+    }
+
+    #[test]
+    pub fn test_closures() {
+        run(&[
+            TestEval {
+                input: String::from("let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(2);"),
+                expected: Object::Int(4)
+            },
+            TestEval {
+                input: String::from("let f = fn() { x }; let x = 5; f();"),
+                expected: Object::Int(5)
+            },
+            TestEval {
+                input: String::from("let x = 1; let f = fn(x) { x }; f(2) + x;"),
+                expected: Object::Int(3)
+            },
+            TestEval {
+                input: String::from("let twice = fn(f, x) { f(f(x)) }; let inc = fn(x) { x + 1 }; twice(inc, 5);"),
+                expected: Object::Int(7)
+            },
+            TestEval {
+                input: String::from("let compose = fn(f, g) { fn(x) { g(f(x)) } }; compose(fn(x) { x * 2 }, fn(x) { x + 1 })(5);"),
+                expected: Object::Int(11)
+            },
+        ]);
+    }
+
+    #[test]
+    pub fn test_recursive_functions() {
+        run(&[
+            TestEval {
+                input: String::from("let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15);"),
+                expected: Object::Int(610)
+            },
+            TestEval {
+                input: String::from("let countdown = fn(n) { if (n == 0) { return 0; } countdown(n - 1) }; countdown(100);"),
+                expected: Object::Int(0)
+            },
+            TestEval {
+                input: String::from("let c = fn(n) { if (n == 0) { 0 } else { c(n - 1) } }; c(5000)"),
+                expected: Object::Int(0)
+            },
+        ]);
+    }
+
+    /// The recursion deeper than `MAX_DEPTH` is an error pointing at the
+    /// call, rather than overflowing the native stack.
+    #[test]
+    pub fn test_call_depth() {
+        match test_eval("let f = fn(n) { f(n + 1) + 1 };\nf(0)") {
+            Object::Error(err) => {
+                assert_eq!(err.message, "stack overflow");
+                assert_eq!(err.span, Some(Span::new(16, 8, 1, 17)));
+            },
+            other => panic!("{:?}", other),
+        }
+        // The depth is back to zero after the error.
+        assert_eq!(test_eval("let c = fn(n) { if (n == 0) { 0 } else { c(n - 1) } }; c(5000)"), Object::Int(0));
+    }
+}
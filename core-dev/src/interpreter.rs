@@ -0,0 +1,406 @@
+use std::convert::{ TryFrom, TryInto };
+use std::fmt;
+use std::fs;
+use std::io::{ self, BufRead };
+use std::path::{ Path, PathBuf };
+
+use super::cache::{ self, CacheError };
+use super::environment::{ Env, Environment };
+use super::eval;
+use super::macro_expansion;
+use super::ast;
+use super::lexer::Lexer;
+use super::object::{ Arity, Builtin, ConversionError, Object, RuntimeError };
+use super::parser::Parser;
+use core_stable::diagnostics::Diagnostic;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    /// The problems found by the lexer and the parser.
+    Parse(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    Conversion(ConversionError),
+    /// A cache file which cannot be loaded.
+    Cache(PathBuf, CacheError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            Error::Parse(diagnostics) => {
+                let diagnostics: Vec<String> = diagnostics
+                    .iter()
+                    .map(|diagnostic| format!("{}:{}: {}", diagnostic.span.line, diagnostic.span.column, diagnostic.message))
+                    .collect();
+                write!(f, "{}", diagnostics.join("\n"))
+            },
+            Error::Runtime(RuntimeError { message, span: Some(span) }) => write!(f, "{}:{}: {}", span.line, span.column, message),
+            Error::Runtime(err) => write!(f, "{}", err),
+            Error::Conversion(err) => write!(f, "{}", err),
+            Error::Cache(path, err) => write!(f, "invalid cache {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConversionError> for Error {
+    fn from(err: ConversionError) -> Error {
+        Error::Conversion(err)
+    }
+}
+
+/// The value of a program, with the warnings found while parsing it, like
+/// the identifiers mixing scripts.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Evaluation {
+    pub value: Object,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// A Rust function which can be called from Monkey, `Args` being the tuple of
+/// its argument types. It is implemented for the functions and closures of up
+/// to 5 arguments whose types can be converted from `Object` (including
+/// `Object` itself), and whose return type can be converted into `Object`. The
+/// results which cannot be converted raise a runtime error.
+pub trait HostFunction<Args> {
+    const ARITY: usize;
+
+    /// Converts `args`, of which there are `ARITY`, and calls the function
+    /// registered as `name`.
+    fn call(&self, name: &str, args: Vec<Object>) -> Object;
+}
+
+macro_rules! impl_host_function {
+    ( $arity:expr $(, $arg:ident $value:ident )* ) => {
+        impl<F, R $(, $arg )*> HostFunction<( $( $arg, )* )> for F
+        where
+            F: Fn( $( $arg ),* ) -> R,
+            R: TryInto<Object>, <R as TryInto<Object>>::Error: fmt::Display,
+            $( $arg: TryFrom<Object>, <$arg as TryFrom<Object>>::Error: fmt::Display, )*
+        {
+            const ARITY: usize = $arity;
+
+            #[allow(unused_variables, unused_mut)]
+            fn call(&self, name: &str, args: Vec<Object>) -> Object {
+                let mut args = args.into_iter();
+                $(
+                    let $value = match $arg::try_from(args.next().unwrap_or(Object::Unit)) {
+                        Ok(value) => value,
+                        Err(err) => return Object::Error(RuntimeError::from(
+                            format!("wrong argument type for `{}`: {}", name, err)
+                        )),
+                    };
+                )*
+                match self( $( $value ),* ).try_into() {
+                    Ok(result) => result,
+                    Err(err) => Object::Error(RuntimeError::from(format!("wrong result type for `{}`: {}", name, err))),
+                }
+            }
+        }
+    };
+}
+
+impl_host_function!(0);
+impl_host_function!(1, A a);
+impl_host_function!(2, A a, B b);
+impl_host_function!(3, A a, B b, C c);
+impl_host_function!(4, A a, B b, C c, D d);
+impl_host_function!(5, A a, B b, C c, D d, E e);
+
+/// Runs Monkey programs from Rust, keeping their global bindings from one
+/// program to the next.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use core_dev::interpreter::Interpreter;
+/// use core_dev::object::Object;
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_global("base", 40i64).unwrap();
+/// interpreter.eval_str("let add = fn(x) { base + x };").unwrap();
+/// let result = interpreter.call_function("add", vec![Object::try_from(2i64).unwrap()]).unwrap();
+/// assert_eq!(i64::try_from(result).unwrap(), 42);
+///
+/// interpreter.register_fn("shout", |text: String| text.to_uppercase() + "!");
+/// assert_eq!(interpreter.eval_str(r#"shout("hi")"#).unwrap().value.to_string(), "HI!");
+/// ```
+pub struct Interpreter {
+    env: Env,
+    /// The macros defined so far, which are expanded before evaluating.
+    macros: Env,
+}
+
+/// Frees the values kept alive by reference cycles, like a closure bound in
+/// the global scope and capturing the scope of a call, which captures the
+/// global scope in turn. The functions taken out of the interpreter no longer
+/// see its bindings afterwards.
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        Environment::clear(&self.env);
+        Environment::clear(&self.macros);
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            env: Environment::new(),
+            macros: Environment::new(),
+        }
+    }
+
+    /// Evaluates `source`, returning the value of its last statement and the
+    /// warnings of the parser. Fails with `Error::Parse`, whose diagnostics
+    /// include the warnings, or `Error::Runtime` only.
+    pub fn eval_str(&mut self, source: &str) -> Result<Evaluation, Error> {
+        self.eval_parsed(Parser::new(String::from(source)))
+    }
+
+    /// Same as `eval_str`, but reads the source from `source` as it is
+    /// parsed, without loading it whole first. Failing to read `source` is
+    /// reported as an `Error::Parse`.
+    pub fn eval_reader<R: BufRead>(&mut self, source: R) -> Result<Evaluation, Error> {
+        self.eval_parsed(Parser::from(Lexer::from_reader(source)))
+    }
+
+    fn eval_parsed(&mut self, mut parser: Parser) -> Result<Evaluation, Error> {
+        let program = parser.parse_program().map_err(Error::Parse)?;
+        let warnings = parser.diagnostics();
+        let value = self.eval_program(program)?;
+        Ok(Evaluation { value, warnings })
+    }
+
+    /// Defines the macros of `program` and expands them, then evaluates it.
+    fn eval_program(&mut self, mut program: ast::Program) -> Result<Object, Error> {
+        macro_expansion::define_macros(&mut program, &self.macros);
+        let program = macro_expansion::expand_macros(program, &self.macros).map_err(Error::Runtime)?;
+        match eval::eval_program(&program, &self.env) {
+            Object::Error(err) => Err(Error::Runtime(err)),
+            result => Ok(result),
+        }
+    }
+
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Evaluation, Error> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+        self.eval_str(&source)
+    }
+
+    /// Same as `eval_file`, but loads the parsed program from `cache` when
+    /// the file has not changed since the cache was written (see
+    /// `cache::parse_program`), along with its warnings.
+    pub fn eval_file_cached<P: AsRef<Path>, C: AsRef<Path>>(&mut self, path: P, cache: C) -> Result<Evaluation, Error> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+        let (program, warnings) = cache::parse_program(&source, cache)?;
+        let value = self.eval_program(program)?;
+        Ok(Evaluation { value, warnings })
+    }
+
+    /// Binds `name` in the global scope, replacing any previous binding.
+    pub fn set_global<T>(&mut self, name: &str, value: T) -> Result<(), Error>
+    where
+        T: TryInto<Object>,
+        ConversionError: From<T::Error>,
+    {
+        let value = value.try_into().map_err(ConversionError::from)?;
+        self.env.borrow_mut().set(String::from(name), value);
+        Ok(())
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.env.borrow().get(name)
+    }
+
+    /// Binds `name` in the global scope to a builtin calling `func`. The
+    /// arguments which cannot be converted to the types `func` takes raise
+    /// a runtime error.
+    pub fn register_fn<Args, F: HostFunction<Args> + 'static>(&mut self, name: &str, func: F) {
+        let registered = String::from(name);
+        let builtin = Builtin::new(name, Arity::Exactly(F::ARITY), move |args| func.call(&registered, args));
+        self.env.borrow_mut().set(String::from(name), Object::Builtin(builtin));
+    }
+
+    /// Calls the function bound to `name`, or the builtin named `name`.
+    pub fn call_function(&mut self, name: &str, args: Vec<Object>) -> Result<Object, Error> {
+        let function = self
+            .get_global(name)
+            .or_else(|| super::builtins::lookup(name).map(Object::Builtin))
+            .ok_or_else(|| Error::Runtime(RuntimeError::from(format!("identifier not found: {}", name))))?;
+        match eval::apply_function(function, args) {
+            Object::Error(err) => Err(Error::Runtime(err)),
+            result => Ok(result),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::rc::Rc;
+
+    #[test]
+    pub fn test_globals() {
+        let mut interpreter = Interpreter::new();
+        let mut config = HashMap::new();
+        config.insert(String::from("retries"), 3i64);
+        interpreter.set_global("config", config).unwrap();
+        interpreter.set_global("names", vec!["a", "b"]).unwrap();
+
+        let result = interpreter.eval_str(r#"let total = config["retries"] * len(names); total"#).unwrap().value;
+        assert_eq!(i64::try_from(result).unwrap(), 6);
+        assert_eq!(interpreter.get_global("total"), Some(Object::Int(6)));
+        assert_eq!(interpreter.get_global("nope"), None);
+        assert_eq!(interpreter.eval_str("let x = 1;").unwrap().value, Object::Unit);
+    }
+
+    #[test]
+    pub fn test_reference_cycles() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("
+            let make = fn() {
+                let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };
+                fn() { down(3) }
+            };
+            let counter = make();
+        ").unwrap();
+        assert_eq!(interpreter.eval_str("counter()").unwrap().value, Object::Int(0));
+
+        // The scope of the call to `make`, captured by `counter` which is
+        // bound in the global scope enclosing it.
+        let scope = match interpreter.get_global("counter") {
+            Some(Object::Function{ env, .. }) => Rc::downgrade(&env),
+            other => panic!("{:?}", other),
+        };
+        assert!(scope.upgrade().is_some());
+        drop(interpreter);
+        assert!(scope.upgrade().is_none());
+    }
+
+    #[test]
+    pub fn test_call_function() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let greet = fn(name) { \"Hello, \" + name }; let one = 1;").unwrap();
+        let result = interpreter.call_function("greet", vec!["Monkey".into()]).unwrap();
+        assert_eq!(String::try_from(result).unwrap(), "Hello, Monkey");
+        let result = interpreter.call_function("range", vec![Object::try_from(3i64).unwrap()]).unwrap();
+        assert_eq!(Vec::<i64>::try_from(result).unwrap(), vec![0, 1, 2]);
+
+        let errors = [
+            interpreter.call_function("greet", vec![]).unwrap_err(),
+            interpreter.call_function("one", vec![]).unwrap_err(),
+            interpreter.call_function("missing", vec![]).unwrap_err(),
+        ];
+        let messages: Vec<String> = errors.iter().map(Error::to_string).collect();
+        assert_eq!(messages, vec![
+            "wrong number of arguments: expected 1, got 0",
+            "not a function: INTEGER",
+            "identifier not found: missing",
+        ]);
+    }
+
+    #[test]
+    pub fn test_register_fn() {
+        let mut interpreter = Interpreter::new();
+        let base = 10;
+        interpreter.register_fn("answer", || 42i64);
+        interpreter.register_fn("httpStatus", |code: i64| -> String {
+            match code {
+                200 => String::from("OK"),
+                404 => String::from("Not Found"),
+                _ => String::from("Unknown"),
+            }
+        });
+        interpreter.register_fn("scale", move |x: f64, factor: i64| x * factor as f64 + base as f64);
+        interpreter.register_fn("total", |values: Vec<i64>| values.iter().sum::<i64>());
+        interpreter.register_fn("describe", |value: Object, times: i64| value.inspect().repeat(times as usize));
+        interpreter.register_fn("fail", |message: String| Object::Error(RuntimeError::from(message)));
+
+        let tests = [
+            ("answer()", Object::Int(42)),
+            ("httpStatus(404)", Object::from("Not Found")),
+            ("scale(1.5, 2)", Object::Float(13.0)),
+            ("scale(1, 2)", Object::Float(12.0)),
+            ("total(range(5))", Object::Int(10)),
+            (r#"describe("a", 2)"#, Object::from("\"a\"\"a\"")),
+            ("type(answer)", Object::from("BUILTIN")),
+            ("let f = fn(g) { g(200) }; f(httpStatus)", Object::from("OK")),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(interpreter.eval_str(input).unwrap().value, *expected, "{}", input);
+        }
+
+        let errors = [
+            (r#"httpStatus("200")"#, "1:1: wrong argument type for `httpStatus`: cannot convert STRING to INTEGER"),
+            ("total([1, true])", "1:1: wrong argument type for `total`: cannot convert BOOLEAN to INTEGER"),
+            ("answer(1)", "1:1: wrong number of arguments to `answer`: expected 0, got 1"),
+            ("scale(1)", "1:1: wrong number of arguments to `scale`: expected 2, got 1"),
+            (r#"fail("oops")"#, "1:1: oops"),
+        ];
+        for (input, expected) in errors.iter() {
+            assert_eq!(interpreter.eval_str(input).unwrap_err().to_string(), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_macros() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let unless = macro(cond, then, otherwise) { quote(if (!(unquote(cond))) { unquote(then) } else { unquote(otherwise) }) };").unwrap();
+        assert_eq!(interpreter.eval_str("unless(1 == 2, 10, 20)").unwrap().value, Object::Int(10));
+        assert_eq!(interpreter.get_global("unless"), None);
+        assert_eq!(
+            interpreter.eval_str("let m = macro() { 1 };\n  m()").unwrap_err().to_string(),
+            "2:3: macro `m` must return a quoted expression, got INTEGER"
+        );
+    }
+
+    #[test]
+    pub fn test_errors() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_str("let = 1;\nlet x = 1 # 2;").unwrap_err().to_string(), "\
+1:5: expected an identifier, found `=`
+2:11: illegal character `#`");
+        assert_eq!(interpreter.eval_str("1;\n  true + 1").unwrap_err().to_string(), "2:3: type mismatch: BOOLEAN + INTEGER");
+        let evaluation = interpreter.eval_str("let p\u{430}ypal = 1;\np\u{430}ypal").unwrap();
+        assert_eq!(evaluation.value, Object::Int(1));
+        assert_eq!(
+            evaluation.warnings.iter().map(|warning| warning.message.as_str()).collect::<Vec<_>>(),
+            vec!["the identifier `p\u{430}ypal` mixes the Latin and Cyrillic scripts"; 2]
+        );
+        assert!(interpreter.eval_str("let x = 1;").unwrap().warnings.is_empty());
+        assert_eq!(interpreter.eval_reader("let a = 20;\na * 2".as_bytes()).unwrap().value, Object::Int(40));
+        assert_eq!(interpreter.eval_reader(&b"1 +\n\xff"[..]).unwrap_err().to_string(), "\
+2:1: cannot read the input: stream did not contain valid UTF-8
+2:1: expected an expression, found end of input");
+        let script = std::env::temp_dir().join(format!("monkey-interpreter-test-{}.mk", std::process::id()));
+        let cache = script.with_extension("mkc");
+        fs::write(&script, "let cached = 40 + 2; cached").unwrap();
+        assert_eq!(interpreter.eval_file_cached(&script, &cache).unwrap().value, Object::Int(42));
+        assert_eq!(interpreter.eval_file_cached(&script, &cache).unwrap().value, Object::Int(42));
+        fs::write(&script, "let p\u{430}ypal = 1;").unwrap();
+        let warnings = interpreter.eval_file_cached(&script, &cache).unwrap().warnings;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(interpreter.eval_file_cached(&script, &cache).unwrap().warnings, warnings);
+        fs::write(&cache, b"MKYC").unwrap();
+        assert!(matches!(interpreter.eval_file_cached(&script, &cache), Err(Error::Cache(_, CacheError::Truncated))));
+        fs::remove_file(&script).unwrap();
+        fs::remove_file(&cache).unwrap();
+        match interpreter.eval_file("does/not/exist.mk") {
+            Err(Error::Io(path, _)) => assert_eq!(path, PathBuf::from("does/not/exist.mk")),
+            other => panic!("{:?}", other)
+        }
+        let err: Error = i64::try_from(Object::Unit).unwrap_err().into();
+        assert_eq!(err.to_string(), "cannot convert UNIT to INTEGER");
+    }
+}
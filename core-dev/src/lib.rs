@@ -2,7 +2,16 @@
 use core_stable::token;
 #[allow(unused_imports)]
 use core_stable::lexer;
-#[allow(unused_imports)]
-mod parser;
-#[allow(unused_imports)]
-mod ast;
\ No newline at end of file
+pub mod parser;
+pub mod ast;
+pub mod object;
+pub mod environment;
+pub mod builtins;
+pub mod eval;
+pub mod macro_expansion;
+pub mod code;
+pub mod compiler;
+pub mod vm;
+pub mod cache;
+pub mod repl;
+pub mod interpreter;
@@ -0,0 +1,192 @@
+use std::rc::Rc;
+
+use super::ast;
+use super::environment::{ Env, Environment };
+use super::eval;
+use super::object::{ Object, RuntimeError };
+
+/// Removes the top-level `let name = macro(...) { ... };` statements from
+/// `program`, binding the macros they define in `env`.
+pub fn define_macros(program: &mut ast::Program, env: &Env) {
+    program.global.retain(|statement| match &statement.kind {
+        ast::StatementKind::Let{ name, value: ast::Node { kind: ast::ExpressionKind::MacroLiteral{ params, body }, .. }, .. } => {
+            env.borrow_mut().set(name.clone(), Object::Macro {
+                params: params.clone(),
+                body: (**body).clone(),
+                env: Rc::clone(env)
+            });
+            false
+        },
+        _ => true
+    });
+}
+
+/// Replaces the calls to the macros bound in `env` by the expression they
+/// return. The arguments are passed quoted, without being evaluated, and
+/// the body of the macro must evaluate to a quoted expression.
+pub fn expand_macros(program: ast::Program, env: &Env) -> Result<ast::Program, RuntimeError> {
+    let mut err = None;
+    let program = program.modify(&mut |expression| {
+        if err.is_some() {
+            return expression;
+        }
+        match expand_macro_call(&expression, env) {
+            Ok(Some(expanded)) => expanded,
+            Ok(None) => expression,
+            Err(expand_err) => {
+                err = Some(expand_err);
+                expression
+            }
+        }
+    });
+    match err {
+        Some(err) => Err(err),
+        None => Ok(program),
+    }
+}
+
+/// The expansion of `expression`, or `None` if it is not a macro call.
+fn expand_macro_call(expression: &ast::Expression, env: &Env) -> Result<Option<ast::Expression>, RuntimeError> {
+    let (name, args) = match &expression.kind {
+        ast::ExpressionKind::CallExpression{ lambda, parameters } => match &lambda.kind {
+            ast::ExpressionKind::Ident(name) => (name, parameters),
+            _ => return Ok(None)
+        },
+        _ => return Ok(None)
+    };
+    let (params, body, macro_env) = match env.borrow().get(name) {
+        Some(Object::Macro{ params, body, env }) => (params, body, env),
+        _ => return Ok(None)
+    };
+    let fail = |message: String| Err(RuntimeError { message, span: Some(expression.span) });
+    if params.len() != args.len() {
+        return fail(format!("wrong number of arguments to macro `{}`: expected {}, got {}", name, params.len(), args.len()));
+    }
+
+    let call_env = Environment::new_enclosed(&macro_env);
+    for (param, arg) in params.into_iter().zip(args) {
+        call_env.borrow_mut().set(param, Object::Quote(arg.clone()));
+    }
+    match eval::eval_expression(&body, &call_env) {
+        Object::Quote(expanded) => Ok(Some(expanded)),
+        Object::Return(value) => match *value {
+            Object::Quote(expanded) => Ok(Some(expanded)),
+            value => fail(format!("macro `{}` must return a quoted expression, got {}", name, value.type_name())),
+        },
+        Object::Error(err) => Err(err),
+        value => fail(format!("macro `{}` must return a quoted expression, got {}", name, value.type_name())),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> ast::Program {
+        Parser::new(String::from(input)).parse_program().unwrap()
+    }
+
+    fn eval_input(input: &str) -> Object {
+        eval::eval_program(&parse(input), &Environment::new())
+    }
+
+    #[test]
+    pub fn test_quote_unquote() {
+        let tests = [
+            ("quote(5)", "QUOTE(5)"),
+            ("quote(5 + 8)", "QUOTE((5 + 8))"),
+            ("quote(foobar + barfoo)", "QUOTE((foobar + barfoo))"),
+            ("quote(unquote(4))", "QUOTE(4)"),
+            ("quote(unquote(4 + 4))", "QUOTE(8)"),
+            ("quote(8 + unquote(4 + 4))", "QUOTE((8 + 8))"),
+            ("quote(unquote(4 + 4) + 8)", "QUOTE((8 + 8))"),
+            ("let foobar = 8; quote(foobar)", "QUOTE(foobar)"),
+            ("let foobar = 8; quote(unquote(foobar))", "QUOTE(8)"),
+            ("quote(unquote(true == false))", "QUOTE(false)"),
+            (r#"quote(unquote("a" + "b") + unquote(1.5))"#, r#"QUOTE(("ab" + 1.5))"#),
+            ("quote(unquote([1, {2: if (false) { 3 }}]))", "QUOTE([1, {2: ()}])"),
+            ("quote(unquote(quote(4 + 4)))", "QUOTE((4 + 4))"),
+            ("let quotedInfix = quote(4 + 4); quote(unquote(4 + 4) + unquote(quotedInfix))", "QUOTE((8 + (4 + 4)))"),
+            ("quote(fn(x) { unquote(1 + 1) })", "QUOTE(fn(x) { 2 })"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval_input(input).inspect(), *expected, "{}", input);
+        }
+
+        let errors = [
+            ("quote(1, 2)", "wrong number of arguments to `quote`: expected 1, got 2"),
+            ("quote(unquote())", "wrong number of arguments to `unquote`: expected 1, got 0"),
+            ("quote(unquote(fn(x) { x }))", "cannot unquote FUNCTION"),
+            ("quote(unquote(1 + true))", "type mismatch: INTEGER + BOOLEAN"),
+            ("unquote(1)", "identifier not found: unquote"),
+        ];
+        for (input, expected) in errors.iter() {
+            assert_eq!(eval_input(input), Object::Error(RuntimeError::from(*expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_define_macros() {
+        let mut program = parse("let number = 1; let function = fn(x, y) { x + y }; let mymacro = macro(x, y) { x + y; };");
+        let env = Environment::new();
+        define_macros(&mut program, &env);
+
+        assert_eq!(program.global.len(), 2);
+        assert_eq!(env.borrow().get("number"), None);
+        assert_eq!(env.borrow().get("function"), None);
+        let defined = env.borrow().get("mymacro");
+        match defined {
+            Some(Object::Macro{ params, body, .. }) => {
+                assert_eq!(params, vec![String::from("x"), String::from("y")]);
+                assert_eq!(body.to_string(), "{ (x + y) }");
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn expand(input: &str) -> Result<ast::Program, RuntimeError> {
+        let mut program = parse(input);
+        let env = Environment::new();
+        define_macros(&mut program, &env);
+        expand_macros(program, &env)
+    }
+
+    #[test]
+    pub fn test_expand_macros() {
+        let tests = [
+            ("let infixExpression = macro() { quote(1 + 2); }; infixExpression();", "(1 + 2)"),
+            ("let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; reverse(2 + 2, 10 - 5);", "((10 - 5) - (2 + 2))"),
+            (
+                r#"let unless = macro(condition, consequence, alternative) {
+                    quote(if (!(unquote(condition))) { unquote(consequence); } else { unquote(alternative); });
+                };
+                unless(2 < 1, puts("not greater"), puts("greater"));"#,
+                r#"if ((!(2 < 1))) { puts("not greater") } else { puts("greater") }"#
+            ),
+            ("let early = macro(x) { return quote(unquote(x) * 2); }; fn() { early(1 + 1) }", "fn() { ((1 + 1) * 2) }"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            assert_eq!(expand(input).unwrap().to_string(), *expected, "{}", input);
+        }
+
+        let errors = [
+            ("let m = macro(x) { x }; m(1, 2)", "wrong number of arguments to macro `m`: expected 1, got 2"),
+            ("let m = macro(x) { 1 }; m(1)", "macro `m` must return a quoted expression, got INTEGER"),
+            ("let m = macro() { 1 + true }; m()", "type mismatch: INTEGER + BOOLEAN"),
+        ];
+        for (input, expected) in errors.iter() {
+            assert_eq!(expand(input).unwrap_err().message, *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_macros_outside_let() {
+        assert_eq!(
+            eval_input("fn() { let m = macro(x) { x }; }()"),
+            Object::Error(RuntimeError::from("macros must be defined by a top-level `let` statement"))
+        );
+    }
+}
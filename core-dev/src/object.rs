@@ -0,0 +1,546 @@
+use std::cell::RefCell;
+use std::cmp::PartialEq;
+use std::collections::{ BTreeMap, HashMap };
+use std::convert::{ Infallible, TryFrom, TryInto };
+use std::fmt;
+use std::rc::Rc;
+
+use super::ast;
+use super::code::Instructions;
+use super::environment::Env;
+use core_stable::diagnostics::Diagnostic;
+use core_stable::span::Span;
+
+/// Called with a number of arguments accepted by the arity of its builtin.
+pub type BuiltinFunction = Rc<dyn Fn(Vec<Object>) -> Object>;
+
+/// How many arguments a builtin takes.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    Between(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::Between(min, max) => min <= count && count <= max,
+            Arity::AtLeast(min) => min <= count,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{}", n),
+            Arity::Between(min, max) if min + 1 == *max => write!(f, "{} or {}", min, max),
+            Arity::Between(min, max) => write!(f, "{} to {}", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {}", min),
+        }
+    }
+}
+
+/// A function implemented in Rust, compared by name.
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: String,
+    pub arity: Arity,
+    pub func: BuiltinFunction,
+}
+
+impl Builtin {
+    pub fn new<F: Fn(Vec<Object>) -> Object + 'static>(name: &str, arity: Arity, func: F) -> Self {
+        Self {
+            name: String::from(name),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builtin").field("name", &self.name).field("arity", &self.arity).finish()
+    }
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// An error raised while evaluating, compared by message.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    /// Span of the expression that raised the error, once the evaluator
+    /// attached it.
+    pub span: Option<Span>,
+}
+
+impl PartialEq for RuntimeError {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> RuntimeError {
+        RuntimeError { message, span: None }
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> RuntimeError {
+        RuntimeError::from(String::from(message))
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Errors without span point at the beginning of the source.
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Diagnostic {
+        Diagnostic::error(err.message.clone(), err.span.unwrap_or(Span::new(0, 0, 1, 1)))
+    }
+}
+
+/// The values usable as hash keys. Their order is the iteration order of
+/// the hashes: booleans, then integers, then strings.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub enum HashKey {
+    Boolean(bool),
+    Int(isize),
+    String(String),
+}
+
+impl HashKey {
+    /// `None` if `object` is not hashable.
+    pub fn from_object(object: &Object) -> Option<HashKey> {
+        match object {
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::Int(value) => Some(HashKey::Int(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None
+        }
+    }
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Object {
+        match key {
+            HashKey::Boolean(value) => Object::Boolean(value),
+            HashKey::Int(value) => Object::Int(value),
+            HashKey::String(value) => Object::String(value),
+        }
+    }
+}
+
+/// A function lowered to bytecode by the compiler.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub params: ast::Parameters,
+    /// The number of local bindings, parameters included.
+    pub num_locals: usize,
+    /// The span of the expression each fallible instruction was compiled
+    /// from, by offset.
+    pub spans: Vec<(usize, Span)>,
+}
+
+impl CompiledFunction {
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        self.spans
+            .binary_search_by_key(&offset, |(start, _)| *start)
+            .ok()
+            .map(|index| self.spans[index].1)
+    }
+}
+
+/// A compiled function with the free variables it captured. Their cells are
+/// shared with the frame which defined them, so that the closure sees the
+/// later redefinitions, as with the evaluator.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Rc<RefCell<Object>>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Object {
+    /// Values
+    Int(isize),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Function{ params: ast::Parameters, body: ast::Expression, env: Env },
+    Builtin(Builtin),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    Array(Vec<Object>),
+    Hash(BTreeMap<HashKey, Object>),
+    Unit,
+
+    /// Macros
+    /// An unevaluated expression, made by `quote`.
+    Quote(ast::Expression),
+    Macro{ params: ast::Parameters, body: ast::Expression, env: Env },
+
+    /// Control flow
+    Return(Box<Object>),
+    Error(RuntimeError),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Int(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::String(_) => "STRING",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::Function{ .. } => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::CompiledFunction(_) => "COMPILED_FUNCTION",
+            Object::Closure(_) => "FUNCTION",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Unit => "UNIT",
+            Object::Quote(_) => "QUOTE",
+            Object::Macro{ .. } => "MACRO",
+            Object::Return(_) => "RETURN_VALUE",
+            Object::Error(_) => "ERROR",
+        }
+    }
+
+    /// The representation of the value as it would be written in Monkey,
+    /// which is what the REPL prints.
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Int(value) => value.to_string(),
+            Object::Float(value) => format!("{:?}", value),
+            Object::String(value) => format!("{:?}", value),
+            Object::Boolean(value) => value.to_string(),
+            Object::Function{ params, .. } => format!("fn({}) {{ ... }}", params.join(", ")),
+            Object::Builtin(builtin) => format!("<builtin {}>", builtin.name),
+            Object::CompiledFunction(function) => format!("<compiled fn({})>", function.params.join(", ")),
+            Object::Closure(closure) => format!("fn({}) {{ ... }}", closure.function.params.join(", ")),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Object::inspect).collect();
+                format!("[{}]", elements.join(", "))
+            },
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", Object::from(key.clone()).inspect(), value.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            },
+            Object::Unit => String::from("()"),
+            Object::Quote(expression) => format!("QUOTE({})", expression),
+            Object::Macro{ params, .. } => format!("macro({}) {{ ... }}", params.join(", ")),
+            Object::Return(value) => value.inspect(),
+            Object::Error(err) => format!("ERROR: {}", err),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Boolean(false) | Object::Unit)
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Object::Error(_))
+    }
+
+    /// Whether the value is an error or a `return`, both of which stop the
+    /// evaluation of the enclosing expressions up to the function call.
+    pub fn is_return_or_error(&self) -> bool {
+        matches!(self, Object::Return(_) | Object::Error(_))
+    }
+}
+
+/// Same as `inspect`, except that strings are written without quotes.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::String(value) => write!(f, "{}", value),
+            Object::Return(value) => write!(f, "{}", value),
+            object => write!(f, "{}", object.inspect())
+        }
+    }
+}
+
+/// A Monkey value which does not have the Rust type it is converted to.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot convert {} to {}", self.found, self.expected)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// For the infallible conversions, in the bounds of the fallible ones.
+impl From<Infallible> for ConversionError {
+    fn from(never: Infallible) -> ConversionError {
+        match never {}
+    }
+}
+
+/// The integers of Monkey are `isize`, which is narrower than `i64` on 32-bit
+/// targets.
+impl TryFrom<i64> for Object {
+    type Error = ConversionError;
+
+    fn try_from(value: i64) -> Result<Object, ConversionError> {
+        match isize::try_from(value) {
+            Ok(value) => Ok(Object::Int(value)),
+            Err(_) => Err(ConversionError { expected: "INTEGER", found: "out-of-range i64" }),
+        }
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Object {
+        Object::Float(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Object {
+        Object::Boolean(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Object {
+        Object::String(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Object {
+        Object::String(String::from(value))
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Object {
+        Object::Unit
+    }
+}
+
+impl<T> TryFrom<Vec<T>> for Object where T: TryInto<Object>, ConversionError: From<T::Error> {
+    type Error = ConversionError;
+
+    fn try_from(values: Vec<T>) -> Result<Object, ConversionError> {
+        let values = values.into_iter().map(|value| value.try_into().map_err(ConversionError::from));
+        Ok(Object::Array(values.collect::<Result<_, _>>()?))
+    }
+}
+
+impl<T> TryFrom<HashMap<String, T>> for Object where T: TryInto<Object>, ConversionError: From<T::Error> {
+    type Error = ConversionError;
+
+    fn try_from(values: HashMap<String, T>) -> Result<Object, ConversionError> {
+        let values = values.into_iter().map(|(key, value)| Ok((HashKey::String(key), value.try_into()?)));
+        Ok(Object::Hash(values.collect::<Result<_, ConversionError>>()?))
+    }
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<i64, ConversionError> {
+        match object {
+            Object::Int(value) => Ok(value as i64),
+            object => Err(ConversionError { expected: "INTEGER", found: object.type_name() }),
+        }
+    }
+}
+
+/// Integers are converted too, as they are promoted when mixed with floats.
+impl TryFrom<Object> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<f64, ConversionError> {
+        match object {
+            Object::Float(value) => Ok(value),
+            Object::Int(value) => Ok(value as f64),
+            object => Err(ConversionError { expected: "FLOAT", found: object.type_name() }),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<bool, ConversionError> {
+        match object {
+            Object::Boolean(value) => Ok(value),
+            object => Err(ConversionError { expected: "BOOLEAN", found: object.type_name() }),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<String, ConversionError> {
+        match object {
+            Object::String(value) => Ok(value),
+            object => Err(ConversionError { expected: "STRING", found: object.type_name() }),
+        }
+    }
+}
+
+impl<T: TryFrom<Object, Error = ConversionError>> TryFrom<Object> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<Vec<T>, ConversionError> {
+        match object {
+            Object::Array(elements) => elements.into_iter().map(T::try_from).collect(),
+            object => Err(ConversionError { expected: "ARRAY", found: object.type_name() }),
+        }
+    }
+}
+
+/// Only the hashes whose keys are all strings can be converted.
+impl<T: TryFrom<Object, Error = ConversionError>> TryFrom<Object> for HashMap<String, T> {
+    type Error = ConversionError;
+
+    fn try_from(object: Object) -> Result<HashMap<String, T>, ConversionError> {
+        match object {
+            Object::Hash(pairs) => pairs
+                .into_iter()
+                .map(|(key, value)| match key {
+                    HashKey::String(key) => Ok((key, T::try_from(value)?)),
+                    key => Err(ConversionError { expected: "STRING", found: Object::from(key).type_name() }),
+                })
+                .collect(),
+            object => Err(ConversionError { expected: "HASH", found: object.type_name() }),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::environment::Environment;
+
+    fn len(args: Vec<Object>) -> Object {
+        Object::Int(args.len() as isize)
+    }
+
+    #[test]
+    pub fn test_inspect() {
+        let tests = [
+            (Object::Int(-5), "-5", "INTEGER"),
+            (Object::Float(1.0), "1.0", "FLOAT"),
+            (Object::Float(2.5), "2.5", "FLOAT"),
+            (Object::String(String::from("a \"b\"")), "\"a \\\"b\\\"\"", "STRING"),
+            (Object::Boolean(true), "true", "BOOLEAN"),
+            (Object::Unit, "()", "UNIT"),
+            (Object::Array(vec![Object::Int(1), Object::String(String::from("a"))]), "[1, \"a\"]", "ARRAY"),
+            (Object::Array(vec![]), "[]", "ARRAY"),
+            (
+                Object::Hash(vec![
+                    (HashKey::String(String::from("name")), Object::String(String::from("monkey"))),
+                    (HashKey::Int(1), Object::Boolean(true)),
+                    (HashKey::Boolean(false), Object::Unit),
+                ].into_iter().collect()),
+                "{false: (), 1: true, \"name\": \"monkey\"}",
+                "HASH"
+            ),
+            (Object::Return(Box::new(Object::Int(1))), "1", "RETURN_VALUE"),
+            (Object::Error(RuntimeError::from("oops")), "ERROR: oops", "ERROR"),
+            (Object::Builtin(Builtin::new("len", Arity::Exactly(1), len)), "<builtin len>", "BUILTIN"),
+            (
+                Object::Quote(ast::ExpressionKind::InfixExpression {
+                    left: Box::new(ast::ExpressionKind::Int(1).into()),
+                    op: ast::Operator::Infix(ast::InfixOperator::Plus),
+                    right: Box::new(ast::ExpressionKind::Ident(String::from("x")).into())
+                }.into()),
+                "QUOTE((1 + x))",
+                "QUOTE"
+            ),
+            (
+                Object::Function {
+                    params: vec![String::from("x"), String::from("y")],
+                    body: ast::ExpressionKind::BlockExpression(vec![]).into(),
+                    env: Environment::new()
+                },
+                "fn(x, y) { ... }",
+                "FUNCTION"
+            ),
+        ];
+
+        for (object, inspected, type_name) in tests.iter() {
+            assert_eq!(object.inspect(), *inspected);
+            assert_eq!(object.type_name(), *type_name);
+        }
+    }
+
+    #[test]
+    pub fn test_conversions() {
+        let mut map = HashMap::new();
+        map.insert(String::from("a"), vec![1i64, 2]);
+        let object = Object::try_from(map.clone()).unwrap();
+        assert_eq!(
+            object,
+            Object::Hash(vec![(
+                HashKey::String(String::from("a")),
+                Object::Array(vec![Object::Int(1), Object::Int(2)])
+            )].into_iter().collect())
+        );
+        assert_eq!(HashMap::<String, Vec<i64>>::try_from(object), Ok(map));
+
+        assert_eq!(Object::try_from(7i64).and_then(i64::try_from), Ok(7));
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(
+            Object::try_from(i64::MAX),
+            Err(ConversionError { expected: "INTEGER", found: "out-of-range i64" })
+        );
+        assert_eq!(f64::try_from(Object::from(1.5)), Ok(1.5));
+        assert_eq!(f64::try_from(Object::Int(2)), Ok(2.0));
+        assert_eq!(bool::try_from(Object::from(true)), Ok(true));
+        assert_eq!(String::try_from(Object::from("hi")), Ok(String::from("hi")));
+        assert_eq!(Object::from(()), Object::Unit);
+
+        assert_eq!(
+            i64::try_from(Object::from("1")),
+            Err(ConversionError { expected: "INTEGER", found: "STRING" })
+        );
+        assert_eq!(
+            Vec::<String>::try_from(Object::Array(vec![Object::from("a"), Object::Int(1)])),
+            Err(ConversionError { expected: "STRING", found: "INTEGER" })
+        );
+        assert_eq!(
+            HashMap::<String, i64>::try_from(Object::Hash(vec![(HashKey::Int(1), Object::Int(1))].into_iter().collect())),
+            Err(ConversionError { expected: "STRING", found: "INTEGER" })
+        );
+        assert_eq!(
+            ConversionError { expected: "ARRAY", found: "HASH" }.to_string(),
+            "cannot convert HASH to ARRAY"
+        );
+    }
+
+    #[test]
+    pub fn test_display() {
+        assert_eq!(Object::String(String::from("hello")).to_string(), "hello");
+        assert_eq!(Object::Int(42).to_string(), "42");
+        assert_eq!(Object::Return(Box::new(Object::String(String::from("hi")))).to_string(), "hi");
+    }
+}
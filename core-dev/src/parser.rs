@@ -1,73 +1,304 @@
-use std::any::Any;
-use std::rc::Rc;
+use std::fmt;
+use std::io::BufRead;
 
 use super::ast;
 use core_stable::token;
 use core_stable::lexer;
+use core_stable::span::Span;
+use core_stable::diagnostics::Diagnostic;
 
 pub struct Parser {
     tokens: Vec<token::Token>,
+    /// The span of each token.
+    spans: Vec<Span>,
+    /// Empty span at the end of the input.
+    eof: Span,
     cur_token: usize,
-    counter: (isize, isize)
+    errors: Vec<ParseError>,
+    /// The problems reported by the lexer.
+    lexer_diagnostics: Vec<Diagnostic>,
 }
 
+/// What the parser was looking for when it failed.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expected {
+    Token(token::Token),
+    OneOf(Vec<token::Token>),
+    Identifier,
+    Expression,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Token(tok) => write!(f, "`{}`", tok),
+            Expected::OneOf(toks) => {
+                let toks: Vec<String> = toks.iter().map(|tok| format!("`{}`", tok)).collect();
+                write!(f, "{}", toks.join(" or "))
+            },
+            Expected::Identifier => write!(f, "an identifier"),
+            Expected::Expression => write!(f, "an expression"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub expected: Expected,
+    /// `None` at the end of the input.
+    pub found: Option<token::Token>,
+    /// Span of the offending token.
+    pub span: Span,
+    /// How to fix the input, when the parser can tell.
+    pub help: Option<String>,
+}
+
+impl ParseError {
+    fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(String::from(help));
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, found ", self.expected)?;
+        match &self.found {
+            Some(token::Illegal(ch)) => write!(f, "illegal character `{}`", ch),
+            Some(tok) => write!(f, "`{}`", tok),
+            None => write!(f, "end of input"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(err.to_string(), err.span)
+            .with_label(format!("expected {}", err.expected));
+        match &err.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Binding power of the operators, from the loosest to the tightest
+/// (see `ressources/parser explained.md`).
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+enum Precedence {
+    Lowest,
+    Equality,
+    Comparison,
+    Sum,
+    Product,
+    Prefix,
+    Suffix,
+}
+
+impl From<&token::Token> for Precedence {
+    fn from(tok: &token::Token) -> Precedence {
+        match tok {
+            token::Eq | token::NotEq => Precedence::Equality,
+            token::LowerThan
+            | token::GreaterThan
+            | token::LowerThanOrEqualTo
+            | token::GreaterThanOrEqualTo => Precedence::Comparison,
+            token::Plus | token::Minus => Precedence::Sum,
+            token::Asterisk | token::Slash => Precedence::Product,
+            token::LeftParen | token::LeftBracket => Precedence::Suffix,
+            _ => Precedence::Lowest,
+        }
+    }
+}
 
 #[allow(dead_code)]
 impl Parser {
+    #[cfg(not(feature = "logos"))]
+    pub fn new(string: String) -> Self {
+        Self::from(lexer::Lexer::new(string))
+    }
+
+    /// Lexes `string` with the lexer generated by logos.
+    #[cfg(feature = "logos")]
     pub fn new(string: String) -> Self {
+        Self::from(core_stable::logos_lexer::LogosLexer::new(&string))
+    }
+
+    fn from_lexed(tokens: Vec<token::Token>, spans: Vec<Span>, eof: Span, lexer_diagnostics: Vec<Diagnostic>) -> Self {
         Self {
-            tokens: lexer::Lexer::new(string).collect(),
+            tokens,
+            spans,
+            eof,
             cur_token: 0,
-            counter: (0, 0),
+            errors: Vec::new(),
+            lexer_diagnostics,
         }
     }
 
     fn get(&self, delta: isize) -> Option<token::Token> {
-        match self.tokens.get((self.cur_token as isize + delta) as usize).clone() {
-            Some(token) => Some(token.clone()),
-            None => None
-        }
+        self.tokens.get((self.cur_token as isize + delta) as usize).cloned()
+    }
+
+    fn span(&self, index: usize) -> Span {
+        self.spans.get(index).cloned().unwrap_or(self.eof)
     }
 
-    pub fn parse_program(&mut self) -> ast::Program {
+    /// A node spanning from the token of index `start` to the last consumed one.
+    fn node<Kind>(&self, kind: Kind, start: usize) -> ast::Node<Kind> {
+        let span = if self.cur_token > start {
+            self.span(start).to(self.span(self.cur_token - 1))
+        } else {
+            self.span(start)
+        };
+        ast::Node::new(kind, span)
+    }
+
+    /// The errors met by the last call to `parse`.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// The problems of the lexer and the errors met by the last call to
+    /// `parse`, in the order of the source. The errors due to an illegal
+    /// character are left out, as the lexer already reported it.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.lexer_diagnostics.clone();
+        diagnostics.extend(
+            self.errors
+                .iter()
+                .filter(|err| !matches!(err.found, Some(token::Illegal(_))))
+                .map(Diagnostic::from)
+        );
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.offset);
+        diagnostics
+    }
+
+    /// Fails on the errors of the parser, but also on the errors of the
+    /// lexer that do not make the parser fail, like an unterminated comment,
+    /// giving them all as `diagnostics` does. The warnings of the lexer, like
+    /// mixed-script identifiers, are left in `diagnostics` on success.
+    pub fn parse_program(&mut self) -> Result<ast::Program, Vec<Diagnostic>> {
         let ast = self.parse();
-        ast::Program::from(ast)
+        if self.errors.is_empty() && !self.lexer_diagnostics.iter().any(Diagnostic::is_error) {
+            Ok(ast::Program::from(ast))
+        } else {
+            Err(self.diagnostics())
+        }
     }
 
+    /// Parses every statement, skipping the invalid ones so that all the
+    /// errors of the input are reported at once.
     pub fn parse(&mut self) -> ast::BlockStatement {
-        let statements = ast::BlockStatement::new();
+        let mut statements = ast::BlockStatement::new();
+        self.errors.clear();
+        while self.get(0).is_some() {
+            let start = self.cur_token;
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => {
+                    self.errors.push(err);
+                    self.synchronize(start);
+                }
+            }
+        }
         statements
     }
 
-    fn parse_statement(&mut self) -> ast::Statement {
-        let ret = match self.tokens.get(self.cur_token) {
-            Some(token::Let) => self.parse_let_statement(),
-            Some(token::Return) => self.parse_return_statement(),
-            Some(_) => self.parse_expression().into(),
-            _ => panic!()
-        };
-        match ret.clone() {
-            ast::Statement::Expr(expr) => {
-                match expr {
-                    ast::Expression::BlockExpression(_) => (),
-                    ast::Expression::IfExpression{ .. } => (),
-                    _ => {
-                        assert_eq!(self.get(0), Some(token::Semicolon));
-                        self.cur_token += 1;
+    /// Skips the statement beginning at `start`: up to the first `;` outside
+    /// of any brace, or to the end of the input.
+    fn synchronize(&mut self, start: usize) {
+        self.cur_token = start;
+        let mut depth = 0;
+        while let Some(tok) = self.get(0) {
+            self.cur_token += 1;
+            match tok {
+                token::LeftBrace => depth += 1,
+                token::RightBrace if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 && !matches!(self.get(0), Some(token::Else) | Some(token::Semicolon)) {
+                        break;
                     }
+                },
+                token::Semicolon if depth == 0 => break,
+                _ => ()
+            }
+        }
+    }
+
+    fn error(&self, expected: Expected) -> ParseError {
+        ParseError {
+            expected,
+            found: self.get(0),
+            span: self.span(self.cur_token),
+            help: None,
+        }
+    }
+
+    fn check(&self, expected: token::Token) -> Result<(), ParseError> {
+        if self.get(0).as_ref() == Some(&expected) {
+            Ok(())
+        } else {
+            Err(self.error(Expected::Token(expected)))
+        }
+    }
+
+    fn consume(&mut self, expected: token::Token) -> Result<(), ParseError> {
+        self.check(expected)?;
+        self.cur_token += 1;
+        Ok(())
+    }
+
+    fn parse_statement(&mut self) -> Result<ast::Statement, ParseError> {
+        let ret = match self.get(0) {
+            Some(token::Let) | Some(token::DocComment(_)) => self.parse_let_statement()?,
+            Some(token::Return) => self.parse_return_statement()?,
+            Some(_) => self.parse_expression()?.into(),
+            None => return Err(self.error(Expected::Expression))
+        };
+        self.parse_end_of(ret)
+    }
+
+    /// Consumes the `;` ending `statement`, which may be omitted after a block
+    /// or an `if`.
+    fn parse_end_of(&mut self, statement: ast::Statement) -> Result<ast::Statement, ParseError> {
+        match &statement.kind {
+            ast::StatementKind::Expr(ast::Node { kind: ast::ExpressionKind::BlockExpression(_), .. })
+            | ast::StatementKind::Expr(ast::Node { kind: ast::ExpressionKind::IfExpression{ .. }, .. }) => {
+                if self.get(0) == Some(token::Semicolon) {
+                    self.cur_token += 1;
                 }
             },
-            _ => {
-                assert_eq!(self.get(0), Some(token::Semicolon));
-                self.cur_token += 1;
-            }
+            ast::StatementKind::Let{ .. } => self.parse_end_of_statement()
+                .map_err(|err| err.with_help("add a `;` after the let statement"))?,
+            ast::StatementKind::Return(_) => self.parse_end_of_statement()
+                .map_err(|err| err.with_help("add a `;` after the return statement"))?,
+            ast::StatementKind::Expr(_) => self.parse_end_of_statement()
+                .map_err(|err| err.with_help("add a `;` between the two expressions"))?,
         };
-        ret
+        Ok(statement)
     }
 
-    fn parse_expression(&mut self) -> ast::Expression {
-        let ret = match self.get(0) {
-            Some(token::Token::LeftBrace) => self.parse_block_statement(),
+    /// The `;` may only be omitted before the end of a block or of the program.
+    fn parse_end_of_statement(&mut self) -> Result<(), ParseError> {
+        match self.get(0) {
+            Some(token::Semicolon) => {
+                self.cur_token += 1;
+                Ok(())
+            },
+            Some(token::RightBrace) | None => Ok(()),
+            Some(_) => Err(self.error(Expected::Token(token::Semicolon)))
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        self.parse_operator_precedance(Precedence::Lowest)
+    }
+
+    fn parse_prefix(&mut self) -> Result<ast::Expression, ParseError> {
+        match self.get(0) {
+            Some(token::Token::LeftBrace) => self.parse_brace_expression(),
 
             Some(token::Token::Int(_))
             | Some(token::Token::String(_))
@@ -75,165 +306,338 @@ impl Parser {
             | Some(token::Token::Unit)
             | Some(token::Token::Float(_)) => self.parse_literal(),
 
-            Some(token::Token::Function) => self.parse_function(),
+            Some(token::Token::Function)
+            | Some(token::Token::Macro) => self.parse_function(),
+
+            Some(token::Token::If) => self.parse_if_expression(),
 
             Some(token::Token::Ident(ident)) => {
                 self.cur_token += 1;
-                ast::Expression::Ident(ident)
+                Ok(self.node(ast::ExpressionKind::Ident(ident), self.cur_token - 1))
             },
             Some(token::Token::LeftParen) => self.parse_grouping_expression(),
-            Some(some) => panic!("{:?}", some),
-            None => panic!()
-        };
-        fn returning(this: &mut Parser, ret: ast::Expression) -> ast::Expression {
-            if this.get(0) == Some(token::Token::LeftParen) {
-                let ret = this.parse_call_expression(ret);
-                returning(this, ret)
-            } else {
-                ret
-            }
+            Some(token::Token::LeftBracket) => self.parse_array_literal(),
+
+            Some(token::Token::Bang)
+            | Some(token::Token::Minus)
+            | Some(token::Token::Plus) => self.parse_prefix_expression(),
+            _ => Err(self.error(Expected::Expression))
         }
-        returning(self, ret)
     }
 
-    fn parse_block_statement(&mut self) -> ast::Expression {
-        assert_eq!(self.get(0), Some(token::LeftBrace));
-        let count = self.counter.0;
-        self.counter.0 += 1;
-        self.cur_token += 1;
-        let mut statements = ast::BlockStatement::new();
-        for _ in self.cur_token..self.tokens.len() {
-            if let Some(token::RightBrace) = self.get(0) {
-                self.counter.0 -= 1;
-                self.cur_token += 1;
-                if count-self.counter.0 == 0 {
-                    break;
-                }
+    fn parse_block_statement(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::LeftBrace)?;
+        self.parse_rest_of_block(start, ast::BlockStatement::new())
+    }
+
+    /// Parses the statements of the block opened at `start` up to its `}`,
+    /// after its first `statements`.
+    fn parse_rest_of_block(&mut self, start: usize, mut statements: ast::BlockStatement) -> Result<ast::Expression, ParseError> {
+        while self.get(0) != Some(token::RightBrace) {
+            if self.get(0).is_none() {
+                return Err(self.error(Expected::Token(token::RightBrace)));
             }
-            statements.push(self.parse_statement());
+            statements.push(self.parse_statement()?);
         }
-        ast::Expression::BlockExpression(statements)
+        self.cur_token += 1;
+        Ok(self.node(ast::ExpressionKind::BlockExpression(statements), start))
     }
 
-    fn parse_let_statement(&mut self) -> ast::Statement {
-        assert_eq!(self.get(0), Some(token::Let));
-        self.cur_token += 1;
-        if let Some(token::Token::Ident(ident)) = self.get(0) {
-            self.cur_token += 1;
-            assert_eq!(self.get(0), Some(token::Assign));
-            self.cur_token += 1;
-            let value = self.parse_expression();
-            assert_eq!(self.get(0), Some(token::Semicolon));
-            ast::Statement::Let {
-                name: ident,
-                value: value
-            }
+    /// `{` opens a hash literal when it is directly closed or when its first
+    /// expression is followed by `:`, and a block otherwise. The first
+    /// expression is parsed once either way, so that nested braces are parsed
+    /// in linear time.
+    fn parse_brace_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::LeftBrace)?;
+        match self.get(0) {
+            Some(token::RightBrace) => return self.parse_hash_literal(start, None),
+            Some(token::Let) | Some(token::DocComment(_)) | Some(token::Return) | None => {
+                return self.parse_rest_of_block(start, ast::BlockStatement::new())
+            },
+            _ => ()
+        }
+        let first = self.parse_expression()?;
+        if self.get(0) == Some(token::Colon) {
+            self.parse_hash_literal(start, Some(first))
         } else {
-            panic!()
+            let statement = self.parse_end_of(first.into())?;
+            self.parse_rest_of_block(start, vec![statement])
         }
     }
 
-    fn parse_return_statement(&mut self) -> ast::Statement {
-        assert_eq!(self.get(0), Some(token::Return));
+    /// Parses the pairs of the hash literal opened at `start` up to its `}`,
+    /// `first_key` being its first key if already parsed.
+    fn parse_hash_literal(&mut self, start: usize, mut first_key: Option<ast::Expression>) -> Result<ast::Expression, ParseError> {
+        let mut pairs = Vec::new();
+        while first_key.is_some() || self.get(0) != Some(token::RightBrace) {
+            let key = match first_key.take() {
+                Some(key) => key,
+                None => self.parse_expression()?
+            };
+            self.consume(token::Colon)?;
+            let value = self.parse_expression()?;
+            pairs.push((key, value));
+            match self.get(0) {
+                Some(token::Comma) => self.cur_token += 1,
+                Some(token::RightBrace) => (),
+                _ => return Err(self.error(Expected::OneOf(vec![token::Comma, token::RightBrace])))
+            }
+        }
         self.cur_token += 1;
-        ast::Statement::Return(self.parse_expression())
+        Ok(self.node(ast::ExpressionKind::HashLiteral(pairs), start))
     }
 
-    fn parse_call_expression(&mut self, lambda: ast::Expression) -> ast::Expression {
-        assert_eq!(self.get(0), Some(token::Token::LeftParen));
-        let mut expressions = Vec::new();
-        if self.get(1) != Some(token::RightParen) {
-            while self.get(0) != Some(token::Token::RightParen) {
-                self.cur_token += 1;
-                expressions.push(self.parse_expression());
-                assert!(match self.get(0) {
-                    Some(token::Token::Comma) | Some(token::RightParen) => true,
-                    _ => false
-                })
-            }
-        } else {
+    /// Parses a `let` statement, along with the `///` comments documenting it.
+    fn parse_let_statement(&mut self) -> Result<ast::Statement, ParseError> {
+        let mut doc: Option<String> = None;
+        while let Some(token::DocComment(line)) = self.get(0) {
+            let line = line.strip_prefix(' ').unwrap_or(&line);
+            doc = Some(match doc {
+                Some(doc) => format!("{}\n{}", doc, line),
+                None => String::from(line),
+            });
             self.cur_token += 1;
         }
-        assert_eq!(self.get(0), Some(token::RightParen));
-        self.cur_token += 1;
-        ast::Expression::CallExpression {
+        let start = self.cur_token;
+        self.consume(token::Let)
+            .map_err(|err| err.with_help("doc comments document the `let` statement following them, use `//` for other comments"))?;
+        let help = "a binding is written `let name = value;`";
+        let name = self.parse_identifier().map_err(|err| err.with_help(help))?;
+        self.consume(token::Assign).map_err(|err| err.with_help(help))?;
+        let value = self.parse_expression()?;
+        Ok(self.node(ast::StatementKind::Let {
+            name,
+            value,
+            doc
+        }, start))
+    }
+
+    fn parse_identifier(&mut self) -> Result<ast::Identifier, ParseError> {
+        match self.get(0) {
+            Some(token::Ident(ident)) => {
+                self.cur_token += 1;
+                Ok(ident)
+            },
+            _ => Err(self.error(Expected::Identifier))
+        }
+    }
+
+    fn parse_return_statement(&mut self) -> Result<ast::Statement, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::Return)?;
+        let value = self.parse_expression()?;
+        Ok(self.node(ast::StatementKind::Return(value), start))
+    }
+
+    fn parse_call_expression(&mut self, lambda: ast::Expression) -> Result<ast::Expression, ParseError> {
+        self.consume(token::LeftParen)?;
+        let expressions = self.parse_expression_list(token::RightParen)?;
+        let span = lambda.span.to(self.span(self.cur_token - 1));
+        Ok(ast::Node::new(ast::ExpressionKind::CallExpression {
             parameters: expressions,
             lambda: Box::new(lambda)
+        }, span))
+    }
+
+    fn parse_array_literal(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::LeftBracket)?;
+        let elements = self.parse_expression_list(token::RightBracket)?;
+        Ok(self.node(ast::ExpressionKind::ArrayLiteral(elements), start))
+    }
+
+    fn parse_index_expression(&mut self, left: ast::Expression) -> Result<ast::Expression, ParseError> {
+        self.consume(token::LeftBracket)?;
+        let index = self.parse_expression()?;
+        self.consume(token::RightBracket)?;
+        let span = left.span.to(self.span(self.cur_token - 1));
+        Ok(ast::Node::new(ast::ExpressionKind::IndexExpression {
+            left: Box::new(left),
+            index: Box::new(index)
+        }, span))
+    }
+
+    /// Parses comma separated expressions up to `end`, which is consumed.
+    fn parse_expression_list(&mut self, end: token::Token) -> Result<Vec<ast::Expression>, ParseError> {
+        let mut expressions = Vec::new();
+        if self.get(0).as_ref() == Some(&end) {
+            self.cur_token += 1;
+            return Ok(expressions);
         }
+        loop {
+            expressions.push(self.parse_expression()?);
+            match self.get(0) {
+                Some(token::Comma) => self.cur_token += 1,
+                Some(ref tok) if *tok == end => {
+                    self.cur_token += 1;
+                    return Ok(expressions);
+                },
+                _ => return Err(self.error(Expected::OneOf(vec![token::Comma, end])))
+            }
+        }
+    }
+
+    fn parse_grouping_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::LeftParen)?;
+        let ret = self.parse_expression()?;
+        self.consume(token::RightParen)?;
+        Ok(self.node(ret.kind, start))
+    }
+
+    fn parse_operator_precedance(&mut self, precedence: Precedence) -> Result<ast::Expression, ParseError> {
+        let mut left = self.parse_prefix()?;
+        while let Some(tok) = self.get(0) {
+            if Precedence::from(&tok) <= precedence {
+                break;
+            }
+            left = match tok {
+                token::Token::LeftParen => self.parse_call_expression(left)?,
+                token::Token::LeftBracket => self.parse_index_expression(left)?,
+                _ => self.parse_infix_expression(left)?
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_prefix_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        let operator = match self.get(0) {
+            Some(token::Bang) => ast::PrefixOperator::Bang,
+            Some(token::Minus) => ast::PrefixOperator::Minus,
+            Some(token::Plus) => ast::PrefixOperator::Plus,
+            _ => return Err(self.error(Expected::OneOf(vec![token::Bang, token::Minus, token::Plus])))
+        };
+        self.cur_token += 1;
+        let right = self.parse_operator_precedance(Precedence::Prefix)?;
+        Ok(self.node(ast::ExpressionKind::PrefixExpression {
+            operator: ast::Operator::Prefix(operator),
+            right: Box::new(right)
+        }, start))
     }
 
-    fn parse_grouping_expression(&mut self) -> ast::Expression {
-        assert_eq!(self.get(0), Some(token::LeftParen));
+    fn parse_infix_expression(&mut self, left: ast::Expression) -> Result<ast::Expression, ParseError> {
+        let tok = self.get(0);
+        let op = match tok {
+            Some(token::Plus) => ast::InfixOperator::Plus,
+            Some(token::Minus) => ast::InfixOperator::Minus,
+            Some(token::Asterisk) => ast::InfixOperator::Mul,
+            Some(token::Slash) => ast::InfixOperator::Div,
+            Some(token::Eq) => ast::InfixOperator::Eq,
+            Some(token::NotEq) => ast::InfixOperator::NotEq,
+            Some(token::LowerThan) => ast::InfixOperator::LT,
+            Some(token::GreaterThan) => ast::InfixOperator::GT,
+            Some(token::LowerThanOrEqualTo) => ast::InfixOperator::LTE,
+            Some(token::GreaterThanOrEqualTo) => ast::InfixOperator::GTE,
+            _ => return Err(self.error(Expected::Expression))
+        };
         self.cur_token += 1;
-        let count = self.counter.1;
-        self.counter.1 += 1;        
-        let ret = self.parse_expression();
-        assert_eq!(self.get(0), Some(token::RightParen));
-        self.counter.1 -= 1;
-        assert_eq!(self.counter.1, count);
-        ret
+        let precedence = tok.as_ref().map(Precedence::from).unwrap_or(Precedence::Lowest);
+        let right = self.parse_operator_precedance(precedence)?;
+        let span = left.span.to(right.span);
+        Ok(ast::Node::new(ast::ExpressionKind::InfixExpression {
+            left: Box::new(left),
+            op: ast::Operator::Infix(op),
+            right: Box::new(right)
+        }, span))
     }
 
-    fn parse_operator_precedance(&mut self) -> ast::Expression {
-        todo!()
+    fn parse_if_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        self.consume(token::If)?;
+        let condition = self.parse_expression()?;
+        let consequence = self.parse_block_statement()?;
+        let alternative = if self.get(0) == Some(token::Else) {
+            self.cur_token += 1;
+            match self.get(0) {
+                Some(token::If) => self.parse_if_expression()?,
+                _ => self.parse_block_statement()?
+            }
+        } else {
+            ast::Node::new(ast::ExpressionKind::Unit, self.span(self.cur_token - 1))
+        };
+        Ok(self.node(ast::ExpressionKind::IfExpression {
+            condition: Box::new(condition),
+            consequence: Box::new(consequence.into()),
+            alternative: Box::new(alternative.into())
+        }, start))
     }
 
-    fn parse_literal(&mut self) -> ast::Expression {
-        let temp = self.get(0);
+    fn parse_literal(&mut self) -> Result<ast::Expression, ParseError> {
+        let literal = match self.get(0) {
+            Some(token::Int(v)) => ast::ExpressionKind::Int(v),
+            Some(token::Float(v)) => ast::ExpressionKind::Float(v),
+            Some(token::String(v)) => ast::ExpressionKind::String(v),
+            Some(token::Boolean(v)) => ast::ExpressionKind::Boolean(v),
+            Some(token::Unit) => ast::ExpressionKind::Unit,
+            _ => return Err(self.error(Expected::Expression))
+        };
         self.cur_token += 1;
-        match temp {
-            Some(token::Int(v)) => ast::Expression::Int(v),
-            Some(token::Float(v)) => ast::Expression::Float(v),
-            Some(token::String(v)) => ast::Expression::String(v),
-            Some(token::Boolean(v)) => ast::Expression::Boolean(v),
-            _ => panic!()
-        }
+        Ok(self.node(literal, self.cur_token - 1))
     }
 
-    fn parse_function(&mut self) -> ast::Expression{
-        assert_eq!(self.get(0), Some(token::Token::Function));
+    /// Parses a function literal, or a macro literal which is written the
+    /// same way after the `macro` keyword.
+    fn parse_function(&mut self) -> Result<ast::Expression, ParseError> {
+        let start = self.cur_token;
+        let keyword = self.get(0);
         self.cur_token += 1;
-        assert_eq!(self.get(0), Some(token::Token::LeftParen));
-        
+        self.consume(token::LeftParen)?;
+
         let mut params = ast::Parameters::new();
-        while self.get(0) != Some(token::Token::RightParen) {
+        if self.get(0) == Some(token::RightParen) {
             self.cur_token += 1;
-            params.push(
-                if let Some(token::Ident(ident)) = self.get(0) {
-                    ident
-                } else {
-                    match self.get(0) {
-                        Some(token::Token::Comma)=> continue,
-                        Some(token::RightParen) => break,
-                        _ => panic!()
-                    };
-                });
+        } else {
+            loop {
+                params.push(self.parse_identifier()?);
+                match self.get(0) {
+                    Some(token::Comma) => self.cur_token += 1,
+                    Some(token::RightParen) => {
+                        self.cur_token += 1;
+                        break;
+                    },
+                    _ => return Err(self.error(Expected::OneOf(vec![token::Comma, token::RightParen])))
+                }
+            }
         }
-        self.cur_token += 1;
+        self.check(token::LeftBrace)?;
 
-        ast::Expression::Function {
-            params: params,
-            body: Box::new(self.parse_expression())
-        }
+        let body = Box::new(self.parse_block_statement()?);
+        let kind = match keyword {
+            Some(token::Macro) => ast::ExpressionKind::MacroLiteral{ params, body },
+            _ => ast::ExpressionKind::Function{ params, body },
+        };
+        Ok(self.node(kind, start))
     }
 }
 
-impl From<lexer::Lexer> for Parser {
-    fn from(lex: lexer::Lexer) -> Parser {
-        Self {
-            tokens: lex.collect(),
-            cur_token: 0,
-            counter: (0, 0)
-        }
+impl<'a, Src: BufRead> From<lexer::Lexer<'a, Src>> for Parser {
+    fn from(mut lex: lexer::Lexer<'a, Src>) -> Parser {
+        let (tokens, spans) = std::iter::from_fn(|| lex.next_spanned()).unzip();
+        Self::from_lexed(tokens, spans, lex.eof_span(), lex.diagnostics().to_vec())
+    }
+}
+
+#[cfg(feature = "logos")]
+impl<'a> From<core_stable::logos_lexer::LogosLexer<'a>> for Parser {
+    fn from(mut lex: core_stable::logos_lexer::LogosLexer<'a>) -> Parser {
+        let (tokens, spans) = std::iter::from_fn(|| lex.next_spanned()).unzip();
+        Self::from_lexed(tokens, spans, lex.eof_span(), lex.diagnostics().to_vec())
     }
 }
 
+/// The tokens have no location: every node has a default span.
 impl From<Vec<token::Token>> for Parser {
     fn from(tokens: Vec<token::Token>) -> Parser {
         Self {
-            tokens: tokens,
+            spans: vec![Span::default(); tokens.len()],
+            tokens,
+            eof: Span::default(),
             cur_token: 0,
-            counter: (0, 0)
+            errors: Vec::new(),
+            lexer_diagnostics: Vec::new(),
         }
     }
 }
@@ -241,12 +645,7 @@ impl From<Vec<token::Token>> for Parser {
 
 #[cfg(test)]
 pub mod test {
-    use std::any::Any;
-    use std::rc::Rc;
-
     use super::*;
-    use ast::{ Statement, Expression };
-    use core_stable::lexer;
 
     struct TestLetStatement {
         pub input: String,
@@ -258,36 +657,45 @@ pub mod test {
         let tests = [
             TestLetStatement {
                 input: String::from("let x = 5;"),
-                expected_ast: ast::Statement::Let {
+                expected_ast: ast::StatementKind::Let {
                     name: String::from("x"),
-                    value: ast::Expression::Int(5)
-                }
+                    value: ast::ExpressionKind::Int(5).into(),
+                    doc: None
+                }.into()
             },
 
             TestLetStatement {
                 input: String::from("let y = true;"),
-                expected_ast: ast::Statement::Let {
+                expected_ast: ast::StatementKind::Let {
                     name: ast::Identifier::from("y"),
-                    value: ast::Expression::Boolean(true)
-                }
+                    value: ast::ExpressionKind::Boolean(true).into(),
+                    doc: None
+                }.into()
             },
 
             TestLetStatement {
                 input: String::from("let foobar = y;"),
-                expected_ast: ast::Statement::Let {
+                expected_ast: ast::StatementKind::Let {
                     name: ast::Identifier::from("foobar"),
-                    value: ast::Expression::Ident(ast::Identifier::from("y"))
-                }
+                    value: ast::ExpressionKind::Ident(ast::Identifier::from("y")).into(),
+                    doc: None
+                }.into()
+            },
+
+            TestLetStatement {
+                input: String::from("/// Adds one.\n///\n///  Indented.\r\nlet foobar = y;"),
+                expected_ast: ast::StatementKind::Let {
+                    name: ast::Identifier::from("foobar"),
+                    value: ast::ExpressionKind::Ident(ast::Identifier::from("y")).into(),
+                    doc: Some(String::from("Adds one.\n\n Indented."))
+                }.into()
             }
         ];
 
         for test in tests.iter() {
-            let statement = Parser::new(test.input.clone()).parse_statement();
+            let statement = Parser::new(test.input.clone()).parse_statement().unwrap();
             println!("Test: {:?}\n\nGot: {:?}\n\n\n", test.expected_ast, statement);
-            assert!(match statement {
-                ast::Statement::Let{ .. } => true,
-                _ => false
-            });
+            assert!(matches!(statement.kind, ast::StatementKind::Let{ .. }));
 
             assert_eq!(statement, test.expected_ast);
         }
@@ -300,15 +708,15 @@ pub mod test {
 
     #[test]
     pub fn test_return_statements() {
-        let tests = vec![
+        let tests = [
             TestReturnStatements {
                 input: String::from("return 5;"),
-                expected_value: ast::Expression::Int(5),
+                expected_value: ast::ExpressionKind::Int(5).into(),
             },
 
             TestReturnStatements {
                 input: String::from("return true;"),
-                expected_value: ast::Expression::Boolean(true)
+                expected_value: ast::ExpressionKind::Boolean(true).into()
             },
 /*
             TestReturnStatements {
@@ -318,9 +726,9 @@ pub mod test {
         ];
 
         for test in tests.iter() {
-            let statement = Parser::new(test.input.clone()).parse_statement();
-            assert!(match statement {
-                ast::Statement::Return(value) => {
+            let statement = Parser::new(test.input.clone()).parse_statement().unwrap();
+            assert!(match statement.kind {
+                ast::StatementKind::Return(value) => {
                     assert_eq!(test.expected_value, value);
                     true
                 },
@@ -771,7 +1179,7 @@ pub mod test {
 
     #[test]
     pub fn test_function_parameter_parsing() {
-        let tests = vec![
+        let tests = [
             TestParametersParsing {
                 input: String::from("fn() {};"),
                 expected_parameters: Vec::new(),
@@ -796,9 +1204,9 @@ pub mod test {
 
         for (i, test) in tests.iter().enumerate() {
             println!("{}", i);
-            let expression = Parser::new(test.input.clone()).parse_expression();
-            match expression {
-                ast::Expression::Function{ ref params, .. } => {
+            let expression = Parser::new(test.input.clone()).parse_expression().unwrap();
+            match expression.kind {
+                ast::ExpressionKind::Function{ ref params, .. } => {
                     println!("Expected length {}, got {} !", test.expected_parameters.len(), params.len());
                     assert_eq!(params.len(), test.expected_parameters.len());
                     test.expected_parameters
@@ -814,46 +1222,304 @@ pub mod test {
         }
     }
 
+    #[test]
+    pub fn test_macro_literal_parsing() {
+        let expression = Parser::new(String::from("macro(x, y) { x + y; }")).parse_expression().unwrap();
+        assert_eq!(expression.kind, ast::ExpressionKind::MacroLiteral {
+            params: vec![String::from("x"), String::from("y")],
+            body: Box::new(ast::ExpressionKind::BlockExpression(vec![
+                ast::StatementKind::Expr(ast::ExpressionKind::InfixExpression {
+                    left: Box::new(ast::ExpressionKind::Ident(String::from("x")).into()),
+                    op: ast::Operator::Infix(ast::InfixOperator::Plus),
+                    right: Box::new(ast::ExpressionKind::Ident(String::from("y")).into())
+                }.into()).into()
+            ]).into())
+        });
+    }
+
     #[test]
     pub fn test_call_expression_parsing() {
         let input = String::from("add(1, foobar, { 45; }); rec()(1);");
         let mut pars = Parser::new(input);
-        let stmnt_1 = pars.parse_statement();
-        match stmnt_1 {
-            ast::Statement::Expr(ast::Expression::CallExpression{ parameters, lambda }) => {
+        let stmnt_1 = pars.parse_statement().unwrap();
+        match stmnt_1.kind {
+            ast::StatementKind::Expr(ast::Node { kind: ast::ExpressionKind::CallExpression{ parameters, lambda }, .. }) => {
                 println!("Parameters: {:?}", parameters);
                 println!("Lambda: {:?}", *lambda);
                 assert_eq!(parameters, vec![
-                    ast::Expression::Int(1),
-                    ast::Expression::Ident(String::from("foobar")),
-                    ast::Expression::BlockExpression(vec![ast::Statement::Expr(ast::Expression::Int(45))])
+                    ast::ExpressionKind::Int(1).into(),
+                    ast::ExpressionKind::Ident(String::from("foobar")).into(),
+                    ast::ExpressionKind::BlockExpression(vec![ast::Expression::from(ast::ExpressionKind::Int(45)).into()]).into()
                 ]);
-                assert_eq!(*lambda, ast::Expression::Ident(String::from("add")));
+                assert_eq!(*lambda, ast::ExpressionKind::Ident(String::from("add")).into());
             },
             _ => panic!()
         };
-        let stmnt_2 = pars.parse_statement();
-        match stmnt_2 {
-            ast::Statement::Expr(ast::Expression::CallExpression{ parameters, lambda }) => {
+        let stmnt_2 = pars.parse_statement().unwrap();
+        match stmnt_2.kind {
+            ast::StatementKind::Expr(ast::Node { kind: ast::ExpressionKind::CallExpression{ parameters, lambda }, .. }) => {
                 println!("Parameters: {:?}", parameters);
                 println!("Lambda: {:?}", *lambda);
-                assert_eq!(parameters, vec![ast::Expression::Int(1)]);
-                match *lambda {
-                    ast::Expression::CallExpression{ parameters, lambda } => {
+                assert_eq!(parameters, vec![ast::ExpressionKind::Int(1).into()]);
+                match lambda.kind {
+                    ast::ExpressionKind::CallExpression{ parameters, lambda } => {
                         assert_eq!(parameters, vec![]);
-                        match *lambda {
-                            ast::Expression::Ident(ident) => {
+                        match lambda.kind {
+                            ast::ExpressionKind::Ident(ident) => {
                                 assert_eq!(ident, String::from("rec"));
                             },
-                            _ => assert!(false)
+                            _ => panic!()
                         }
                     },
-                    _ => assert!(false)
+                    _ => panic!()
                 }
             }
-            _ => assert!(false)
+            _ => panic!()
         }
     }
+    /// Span of a single line source.
+    fn span(offset: usize, len: usize) -> Span {
+        Span::new(offset, len, 1, offset + 1)
+    }
+
+    #[test]
+    pub fn test_parse_errors() {
+        let tests = [
+            (
+                "let = 5;",
+                vec![ParseError { expected: Expected::Identifier, found: Some(token::Assign), span: span(4, 1), help: Some(String::from("a binding is written `let name = value;`")) }]
+            ),
+            (
+                "let x 5; let y = ; let z = 1;",
+                vec![
+                    ParseError { expected: Expected::Token(token::Assign), found: Some(token::Int(5)), span: span(6, 1), help: Some(String::from("a binding is written `let name = value;`")) },
+                    ParseError { expected: Expected::Expression, found: Some(token::Semicolon), span: span(17, 1), help: None },
+                ]
+            ),
+            (
+                "add(1 2); fn(x y) { x }; if (x) { let; } else { 1 }; 3",
+                vec![
+                    ParseError { expected: Expected::OneOf(vec![token::Comma, token::RightParen]), found: Some(token::Int(2)), span: span(6, 1), help: None },
+                    ParseError { expected: Expected::OneOf(vec![token::Comma, token::RightParen]), found: Some(token::Ident(String::from("y"))), span: span(15, 1), help: None },
+                    ParseError { expected: Expected::Identifier, found: Some(token::Semicolon), span: span(37, 1), help: Some(String::from("a binding is written `let name = value;`")) },
+                ]
+            ),
+            (
+                "{ 1; ",
+                vec![ParseError { expected: Expected::Token(token::RightBrace), found: None, span: span(5, 0), help: None }]
+            ),
+            (
+                "1 2",
+                vec![ParseError { expected: Expected::Token(token::Semicolon), found: Some(token::Int(2)), span: span(2, 1), help: Some(String::from("add a `;` between the two expressions")) }]
+            ),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let mut parser = Parser::new(String::from(*input));
+            let result = parser.parse_program();
+            assert_eq!(result, Err(parser.diagnostics()), "{}", input);
+            assert_eq!(parser.errors(), &expected[..], "{}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_parse_error_messages() {
+        let errors = Parser::new(String::from("let x = ; let 5 = 1; (1; 3 # 4")).parse_program().unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "expected an expression, found `;`",
+            "expected an identifier, found `5`",
+            "expected `)`, found `;`",
+            "illegal character `#`",
+        ]);
+    }
+
+    #[test]
+    pub fn test_comments() {
+        let program = Parser::new(String::from("// sum\nlet x = 1 /* + 2 */ + 3;\n/// The double.\nlet y = x * 2;")).parse_program().unwrap();
+        assert_eq!(program.to_string(), "let x = (1 + 3); let y = (x * 2)");
+        assert_eq!(program.global[1].span, Span::new(48, 13, 4, 1));
+
+        let mut parser = Parser::new(String::from("1 + 2 /* /* */"));
+        let errors = parser.parse_program().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_error());
+        assert!(parser.errors().is_empty());
+
+        let mut parser = Parser::new(String::from("/// Not a let.\n1;\nlet x = /// nor this.\n2;"));
+        assert!(parser.parse_program().is_err());
+        assert_eq!(parser.diagnostics(), vec![
+            Diagnostic::error("expected `let`, found `1`", Span::new(15, 1, 2, 1))
+                .with_label("expected `let`")
+                .with_help("doc comments document the `let` statement following them, use `//` for other comments"),
+            Diagnostic::error("expected an expression, found `/// nor this.`", Span::new(26, 13, 3, 9))
+                .with_label("expected an expression"),
+        ]);
+    }
+
+    #[test]
+    pub fn test_mixed_script_identifiers() {
+        let mut parser = Parser::new(String::from("let p\u{430}ypal = 1;"));
+        let program = parser.parse_program().unwrap();
+        assert_eq!(program.to_string(), "let p\u{430}ypal = 1");
+        let diagnostics = parser.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].span, span(4, 7));
+    }
+
+    #[test]
+    pub fn test_diagnostics() {
+        let mut parser = Parser::new(String::from("let x = 1 # 2;\nlet y 3;"));
+        assert!(parser.parse_program().is_err());
+        assert_eq!(parser.diagnostics(), vec![
+            Diagnostic::error("illegal character `#`", span(10, 1))
+                .with_label("not part of the language"),
+            Diagnostic::error("expected `=`, found `3`", Span::new(21, 1, 2, 7))
+                .with_label("expected `=`")
+                .with_help("a binding is written `let name = value;`"),
+        ]);
+    }
+
+    #[test]
+    pub fn test_node_spans() {
+        let program = Parser::new(String::from("let x = 1 + foo(2);\n-(x)")).parse_program().unwrap();
+        assert_eq!(program.global[0].span, span(0, 18));
+        match &program.global[0].kind {
+            ast::StatementKind::Let{ value, .. } => {
+                assert_eq!(value.span, span(8, 10));
+                match &value.kind {
+                    ast::ExpressionKind::InfixExpression{ left, right, .. } => {
+                        assert_eq!(left.span, span(8, 1));
+                        assert_eq!(right.span, span(12, 6));
+                    },
+                    other => panic!("{:?}", other)
+                }
+            },
+            other => panic!("{:?}", other)
+        }
+        assert_eq!(program.global[1].span, Span::new(20, 4, 2, 1));
+    }
+
+    #[test]
+    pub fn test_prefix_and_infix_expression_parsing() {
+        let statements = Parser::new(String::from("-a * b + c; if (a < b) { a } else { b }")).parse_program().unwrap().global;
+        let ident = |name: &str| -> Box<ast::Expression> { Box::new(ast::ExpressionKind::Ident(String::from(name)).into()) };
+        assert_eq!(statements, vec![
+            ast::StatementKind::Expr(ast::ExpressionKind::InfixExpression {
+                left: Box::new(ast::ExpressionKind::InfixExpression {
+                    left: Box::new(ast::ExpressionKind::PrefixExpression {
+                        operator: ast::Operator::Prefix(ast::PrefixOperator::Minus),
+                        right: ident("a")
+                    }.into()),
+                    op: ast::Operator::Infix(ast::InfixOperator::Mul),
+                    right: ident("b")
+                }.into()),
+                op: ast::Operator::Infix(ast::InfixOperator::Plus),
+                right: ident("c")
+            }.into()).into(),
+            ast::StatementKind::Expr(ast::ExpressionKind::IfExpression {
+                condition: Box::new(ast::ExpressionKind::InfixExpression {
+                    left: ident("a"),
+                    op: ast::Operator::Infix(ast::InfixOperator::LT),
+                    right: ident("b")
+                }.into()),
+                consequence: Box::new(ast::StatementKind::Expr(ast::ExpressionKind::BlockExpression(vec![
+                    ast::StatementKind::Expr(*ident("a")).into()
+                ]).into()).into()),
+                alternative: Box::new(ast::StatementKind::Expr(ast::ExpressionKind::BlockExpression(vec![
+                    ast::StatementKind::Expr(*ident("b")).into()
+                ]).into()).into())
+            }.into()).into()
+        ]);
+    }
+
+    #[test]
+    pub fn test_array_and_index_expression_parsing() {
+        let statements = Parser::new(String::from("[1, 2 * 3, []]; a * b[1 + 1]; f(x)[0][-1]")).parse_program().unwrap().global;
+        let ident = |name: &str| -> Box<ast::Expression> { Box::new(ast::ExpressionKind::Ident(String::from(name)).into()) };
+        let int = |value: isize| -> Box<ast::Expression> { Box::new(ast::ExpressionKind::Int(value).into()) };
+        let infix = |left, op, right| -> Box<ast::Expression> {
+            Box::new(ast::ExpressionKind::InfixExpression { left, op: ast::Operator::Infix(op), right }.into())
+        };
+        assert_eq!(statements, vec![
+            ast::StatementKind::Expr(ast::ExpressionKind::ArrayLiteral(vec![
+                *int(1),
+                *infix(int(2), ast::InfixOperator::Mul, int(3)),
+                ast::ExpressionKind::ArrayLiteral(vec![]).into()
+            ]).into()).into(),
+            ast::StatementKind::Expr(*infix(
+                ident("a"),
+                ast::InfixOperator::Mul,
+                Box::new(ast::ExpressionKind::IndexExpression {
+                    left: ident("b"),
+                    index: infix(int(1), ast::InfixOperator::Plus, int(1))
+                }.into())
+            )).into(),
+            ast::StatementKind::Expr(ast::ExpressionKind::IndexExpression {
+                left: Box::new(ast::ExpressionKind::IndexExpression {
+                    left: Box::new(ast::ExpressionKind::CallExpression {
+                        lambda: ident("f"),
+                        parameters: vec![*ident("x")]
+                    }.into()),
+                    index: int(0)
+                }.into()),
+                index: Box::new(ast::ExpressionKind::PrefixExpression {
+                    operator: ast::Operator::Prefix(ast::PrefixOperator::Minus),
+                    right: int(1)
+                }.into())
+            }.into()).into()
+        ]);
+
+        let errors = Parser::new(String::from("[1, 2; a[1;")).parse_program().unwrap_err();
+        assert_eq!(errors.iter().map(|err| err.message.as_str()).collect::<Vec<&str>>(), vec![
+            "expected `,` or `]`, found `;`",
+            "expected `]`, found `;`",
+        ]);
+    }
+
+    #[test]
+    pub fn test_hash_literal_parsing() {
+        let statements = Parser::new(String::from(r#"{"one": 1, 2 + 2: [], true: {},}; {}; { x }; {x: {y}}"#)).parse_program().unwrap().global;
+        let ident = |name: &str| -> ast::Expression { ast::ExpressionKind::Ident(String::from(name)).into() };
+        let int = |value: isize| -> ast::Expression { ast::ExpressionKind::Int(value).into() };
+        assert_eq!(statements, vec![
+            ast::StatementKind::Expr(ast::ExpressionKind::HashLiteral(vec![
+                (ast::ExpressionKind::String(String::from("one")).into(), int(1)),
+                (
+                    ast::ExpressionKind::InfixExpression {
+                        left: Box::new(int(2)),
+                        op: ast::Operator::Infix(ast::InfixOperator::Plus),
+                        right: Box::new(int(2))
+                    }.into(),
+                    ast::ExpressionKind::ArrayLiteral(vec![]).into()
+                ),
+                (ast::ExpressionKind::Boolean(true).into(), ast::ExpressionKind::HashLiteral(vec![]).into()),
+            ]).into()).into(),
+            ast::StatementKind::Expr(ast::ExpressionKind::HashLiteral(vec![]).into()).into(),
+            ast::StatementKind::Expr(ast::ExpressionKind::BlockExpression(vec![
+                ast::StatementKind::Expr(ident("x")).into()
+            ]).into()).into(),
+            ast::StatementKind::Expr(ast::ExpressionKind::HashLiteral(vec![(
+                ident("x"),
+                ast::ExpressionKind::BlockExpression(vec![ast::StatementKind::Expr(ident("y")).into()]).into()
+            )]).into()).into(),
+        ]);
+
+        let errors = Parser::new(String::from(r#"{"a": 1 "b": 2}; {"a" 1}"#)).parse_program().unwrap_err();
+        assert_eq!(errors.iter().map(|err| err.message.as_str()).collect::<Vec<&str>>(), vec![
+            "expected `,` or `}`, found `\"b\"`",
+            "expected `;`, found `1`",
+        ]);
+
+        // Each brace is parsed once, whatever the nesting.
+        let depth = 200;
+        let blocks = format!("{}1{}", "{".repeat(depth), "}".repeat(depth));
+        let expected = format!("{}1{}", "{ ".repeat(depth), " }".repeat(depth));
+        assert_eq!(Parser::new(blocks).parse_program().unwrap().to_string(), expected);
+        let hashes = format!("{}2{}", "{1: ".repeat(depth), "}".repeat(depth));
+        assert!(Parser::new(hashes).parse_program().is_ok());
+    }
 /*
     pub fn test_infix_expression<T>(expression: Box<dyn ast::Expression<T = T>>, left: Box<dyn Any>, operator: String, right: Box<dyn Any>) -> bool {
         let op_exp = expression as ast::InfixExpression<_, _>;
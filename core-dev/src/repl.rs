@@ -1,7 +1,123 @@
-use crate::lexer;
-use crate::parser;
-use std::io;
-const PROMPT: &'static str = ">> ";
-
-pub fn start() {
-}
\ No newline at end of file
+use crate::lexer;
+use crate::token;
+use crate::interpreter::{ Error, Interpreter };
+use crate::object::Object;
+use core_stable::diagnostics::{ Diagnostic, Renderer };
+use std::io::{ self, BufRead, IsTerminal, Write };
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+/// The name of the source in the diagnostics.
+const FILE_NAME: &str = "<repl>";
+
+pub fn start() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let renderer = if stdout.is_terminal() { Renderer::colored() } else { Renderer::plain() };
+    if let Err(err) = run(stdin.lock(), stdout.lock(), renderer) {
+        eprintln!("{}", err);
+    }
+}
+
+/// Reads programs from `input` until its end, and writes the value of each of
+/// them to `output`, or the diagnostics rendered by `renderer`. The bindings are
+/// kept from one program to the next.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W, renderer: Renderer) -> io::Result<()> {
+    let mut interpreter = Interpreter::new();
+    let mut buffer = String::new();
+    loop {
+        write!(output, "{}", if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT })?;
+        output.flush()?;
+        if input.read_line(&mut buffer)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+        if is_incomplete(&buffer) {
+            continue;
+        }
+        let source = std::mem::take(&mut buffer);
+        let diagnostics = match interpreter.eval_str(&source) {
+            Ok(evaluation) => {
+                if evaluation.value != Object::Unit {
+                    writeln!(output, "{}", evaluation.value.inspect())?;
+                }
+                evaluation.warnings
+            },
+            Err(Error::Parse(diagnostics)) => diagnostics,
+            Err(Error::Runtime(err)) => vec![Diagnostic::from(&err)],
+            Err(err) => unreachable!("{}", err),
+        };
+        for diagnostic in diagnostics {
+            write!(output, "{}", renderer.render(&diagnostic, FILE_NAME, &source))?;
+        }
+    }
+}
+
+/// Whether some brace, parenthesis or bracket is still open, in which case the next
+/// line is read as the continuation of the same program.
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    for tok in lexer::Lexer::new(String::from(source)) {
+        match tok {
+            token::LeftBrace | token::LeftParen | token::LeftBracket => depth += 1,
+            token::RightBrace | token::RightParen | token::RightBracket => depth -= 1,
+            _ => ()
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn run_session(input: &str) -> String {
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output, Renderer::plain()).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    pub fn test_bindings_are_kept() {
+        assert_eq!(
+            run_session("let x = 5;\nx * 2\n"),
+            ">> >> 10\n>> \n"
+        );
+    }
+
+    #[test]
+    pub fn test_multiline_input() {
+        assert_eq!(
+            run_session("let add = fn(x, y) {\n  x + y\n};\nadd(1,\n2)\n"),
+            ">> .. .. >> .. 3\n>> \n"
+        );
+    }
+
+    #[test]
+    pub fn test_errors_do_not_end_the_session() {
+        assert_eq!(
+            run_session("foo\nlet = ;\n1 + 1\n"),
+            "\
+>> error: identifier not found: foo
+ --> <repl>:1:1
+  |
+1 | foo
+  | ^^^
+>> error: expected an identifier, found `=`
+ --> <repl>:1:5
+  |
+1 | let = ;
+  |     ^ expected an identifier
+  |
+  = help: a binding is written `let name = value;`
+>> 2
+>> \n"
+        );
+    }
+
+    #[test]
+    pub fn test_warnings() {
+        let output = run_session("let p\u{430}ypal = 1; p\u{430}ypal\n");
+        assert!(output.starts_with(">> 1\nwarning: the identifier `p\u{430}ypal` mixes the Latin and Cyrillic scripts\n"), "{}", output);
+    }
+}
@@ -0,0 +1,433 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::iter;
+use std::rc::Rc;
+
+use super::ast;
+use super::builtins;
+use super::code::{ self, Opcode };
+use super::compiler::Bytecode;
+use super::eval;
+use super::object::{ Closure, HashKey, Object, RuntimeError };
+
+/// The deepest the calls can nest before the VM gives up.
+pub const MAX_FRAMES: usize = 1 << 16;
+
+struct Frame {
+    closure: Rc<Closure>,
+    /// The offset of the next instruction.
+    ip: usize,
+    /// The position on the stack of the function called, which its return
+    /// value replaces.
+    base: usize,
+    /// The cells of the locals, arguments first, which the closures created
+    /// by the call share.
+    locals: Vec<Rc<RefCell<Object>>>,
+}
+
+/// Runs compiled programs on a stack: the operands of an instruction are
+/// popped from it and its result pushed back. A call moves the arguments
+/// into the locals of its frame.
+pub struct Vm {
+    constants: Vec<Object>,
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    /// The cells put aside for the next closure.
+    captured: Vec<Rc<RefCell<Object>>>,
+    main: Rc<Closure>,
+}
+
+/// Runs `bytecode` on a new VM.
+pub fn run(bytecode: Bytecode) -> Result<Object, RuntimeError> {
+    Vm::new(bytecode).run()
+}
+
+fn infix_operator(op: Opcode) -> ast::Operator {
+    ast::Operator::Infix(match op {
+        Opcode::Add => ast::InfixOperator::Plus,
+        Opcode::Sub => ast::InfixOperator::Minus,
+        Opcode::Mul => ast::InfixOperator::Mul,
+        Opcode::Div => ast::InfixOperator::Div,
+        Opcode::Equal => ast::InfixOperator::Eq,
+        Opcode::NotEqual => ast::InfixOperator::NotEq,
+        Opcode::LowerThan => ast::InfixOperator::LT,
+        Opcode::GreaterThan => ast::InfixOperator::GT,
+        Opcode::LowerThanOrEqual => ast::InfixOperator::LTE,
+        _ => ast::InfixOperator::GTE,
+    })
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Self {
+        Self {
+            constants: bytecode.constants,
+            // The globals named after a builtin are the builtin until they
+            // are defined.
+            globals: bytecode.globals.iter()
+                .map(|name| builtins::lookup(name).map(Object::Builtin))
+                .collect(),
+            global_names: bytecode.globals,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            captured: Vec::new(),
+            main: Rc::new(Closure {
+                function: bytecode.main,
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("the stack is empty")
+    }
+
+    /// Runs the program, returning the value of its last statement. The
+    /// operators, the index expressions and the builtins behave as in the
+    /// evaluator, and raise the same errors.
+    pub fn run(&mut self) -> Result<Object, RuntimeError> {
+        let mut frame = Frame { closure: Rc::clone(&self.main), ip: 0, base: 0, locals: Vec::new() };
+        'frames: loop {
+            let closure = Rc::clone(&frame.closure);
+            let instructions = &closure.function.instructions;
+            let mut ip = frame.ip;
+
+            while ip < instructions.len() {
+                let start = ip;
+                let op = Opcode::from_byte(instructions[ip]).expect("unknown opcode");
+                ip += 1;
+
+                // Attaches the span of the failing instruction to the errors.
+                macro_rules! check {
+                    ( $result:expr ) => {
+                        match $result {
+                            Object::Error(mut err) => {
+                                if err.span.is_none() {
+                                    err.span = closure.function.span_at(start);
+                                }
+                                return Err(err);
+                            },
+                            result => result,
+                        }
+                    };
+                }
+                macro_rules! fail {
+                    ( $($arg:tt)* ) => {
+                        return Err(RuntimeError {
+                            message: format!($($arg)*),
+                            span: closure.function.span_at(start),
+                        })
+                    };
+                }
+                macro_rules! operand {
+                    ( $width:expr ) => {{
+                        let operand = code::read_operand(&instructions[ip..], $width);
+                        ip += $width;
+                        operand
+                    }};
+                }
+
+                match op {
+                    Opcode::Constant => {
+                        let index = operand!(2);
+                        self.stack.push(self.constants[index].clone());
+                    },
+                    Opcode::Pop => {
+                        self.pop();
+                    },
+                    Opcode::True => self.stack.push(Object::Boolean(true)),
+                    Opcode::False => self.stack.push(Object::Boolean(false)),
+                    Opcode::Unit => self.stack.push(Object::Unit),
+                    Opcode::Add
+                    | Opcode::Sub
+                    | Opcode::Mul
+                    | Opcode::Div
+                    | Opcode::Equal
+                    | Opcode::NotEqual
+                    | Opcode::LowerThan
+                    | Opcode::GreaterThan
+                    | Opcode::LowerThanOrEqual
+                    | Opcode::GreaterThanOrEqual => {
+                        let right = self.pop();
+                        let left = self.pop();
+                        let result = check!(eval::eval_infix_expression(&infix_operator(op), left, right));
+                        self.stack.push(result);
+                    },
+                    Opcode::Minus | Opcode::Plus | Opcode::Bang => {
+                        let operator = ast::Operator::Prefix(match op {
+                            Opcode::Minus => ast::PrefixOperator::Minus,
+                            Opcode::Plus => ast::PrefixOperator::Plus,
+                            _ => ast::PrefixOperator::Bang,
+                        });
+                        let right = self.pop();
+                        let result = check!(eval::eval_prefix_expression(&operator, right));
+                        self.stack.push(result);
+                    },
+                    Opcode::Jump => {
+                        ip = code::read_operand(&instructions[ip..], 2);
+                    },
+                    Opcode::JumpNotTruthy => {
+                        let target = operand!(2);
+                        if !self.pop().is_truthy() {
+                            ip = target;
+                        }
+                    },
+                    Opcode::GetGlobal => {
+                        let index = operand!(2);
+                        let value = match &self.globals[index] {
+                            Some(value) => value.clone(),
+                            None => fail!("identifier not found: {}", self.global_names[index]),
+                        };
+                        self.stack.push(value);
+                    },
+                    Opcode::SetGlobal => {
+                        let index = operand!(2);
+                        self.globals[index] = Some(self.pop());
+                    },
+                    Opcode::GetLocal => {
+                        let index = operand!(1);
+                        self.stack.push(frame.locals[index].borrow().clone());
+                    },
+                    Opcode::SetLocal => {
+                        let index = operand!(1);
+                        *frame.locals[index].borrow_mut() = self.pop();
+                    },
+                    Opcode::GetFree => {
+                        let index = operand!(1);
+                        self.stack.push(closure.free[index].borrow().clone());
+                    },
+                    Opcode::CaptureLocal => {
+                        let index = operand!(1);
+                        self.captured.push(Rc::clone(&frame.locals[index]));
+                    },
+                    Opcode::CaptureFree => {
+                        let index = operand!(1);
+                        self.captured.push(Rc::clone(&closure.free[index]));
+                    },
+                    Opcode::Capture => {
+                        let value = self.pop();
+                        self.captured.push(Rc::new(RefCell::new(value)));
+                    },
+                    Opcode::CurrentClosure => self.stack.push(Object::Closure(Rc::clone(&closure))),
+                    Opcode::Array => {
+                        let count = operand!(2);
+                        let elements = self.stack.split_off(self.stack.len() - count);
+                        self.stack.push(Object::Array(elements));
+                    },
+                    Opcode::Hash => {
+                        let count = operand!(2);
+                        let mut values = self.stack.split_off(self.stack.len() - 2 * count).into_iter();
+                        let mut hash = BTreeMap::new();
+                        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                            let key = match HashKey::from_object(&key) {
+                                Some(key) => key,
+                                None => fail!("unusable as hash key: {}", key.type_name()),
+                            };
+                            hash.insert(key, value);
+                        }
+                        self.stack.push(Object::Hash(hash));
+                    },
+                    Opcode::Index => {
+                        let index = self.pop();
+                        let left = self.pop();
+                        let result = check!(eval::eval_index_expression(left, index));
+                        self.stack.push(result);
+                    },
+                    Opcode::Call => {
+                        let count = operand!(1);
+                        let position = self.stack.len() - 1 - count;
+                        let callee = match &self.stack[position] {
+                            Object::Closure(callee) => Rc::clone(callee),
+                            _ => {
+                                let args = self.stack.split_off(position + 1);
+                                let function = self.pop();
+                                let result = check!(eval::apply_function(function, args));
+                                self.stack.push(result);
+                                continue;
+                            }
+                        };
+                        if callee.function.params.len() != count {
+                            fail!("wrong number of arguments: expected {}, got {}", callee.function.params.len(), count);
+                        }
+                        if self.frames.len() >= MAX_FRAMES {
+                            fail!("stack overflow");
+                        }
+                        frame.ip = ip;
+                        let locals = self.stack
+                            .split_off(position + 1)
+                            .into_iter()
+                            .chain(iter::repeat(Object::Unit))
+                            .take(callee.function.num_locals)
+                            .map(|value| Rc::new(RefCell::new(value)))
+                            .collect();
+                        self.frames.push(frame);
+                        frame = Frame { closure: callee, ip: 0, base: position, locals };
+                        continue 'frames;
+                    },
+                    Opcode::ReturnValue => {
+                        let value = self.pop();
+                        match self.frames.pop() {
+                            Some(caller) => {
+                                self.stack.truncate(frame.base);
+                                self.stack.push(value);
+                                frame = caller;
+                                continue 'frames;
+                            },
+                            None => return Ok(value),
+                        }
+                    },
+                    Opcode::Closure => {
+                        let index = operand!(2);
+                        let count = operand!(1);
+                        let function = match &self.constants[index] {
+                            Object::CompiledFunction(function) => Rc::clone(function),
+                            other => panic!("not a function constant: {:?}", other),
+                        };
+                        let free = self.captured.split_off(self.captured.len() - count);
+                        self.stack.push(Object::Closure(Rc::new(Closure { function, free })));
+                    },
+                }
+            }
+
+            // Only the main program runs off the end of its instructions, the
+            // functions end with `ReturnValue`.
+            return Ok(self.stack.pop().unwrap_or(Object::Unit));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::compiler;
+    use crate::environment::Environment;
+    use crate::parser::Parser;
+    use core_stable::span::Span;
+
+    fn run_input(input: &str) -> Result<Object, RuntimeError> {
+        let program = Parser::new(String::from(input)).parse_program().unwrap();
+        run(compiler::compile(&program).unwrap())
+    }
+
+    fn eval_input(input: &str) -> Object {
+        let program = Parser::new(String::from(input)).parse_program().unwrap();
+        eval::eval_program(&program, &Environment::new())
+    }
+
+    /// The VM and the evaluator agree on the values and on the errors, spans
+    /// included.
+    #[test]
+    pub fn test_same_results_as_eval() {
+        let tests = [
+            "1 + 2 * 3 - 4 / 2",
+            "(5 + 10 * 2 + 15 / 3) * 2 + -10",
+            "-5; --10; +3; 2.5E3 + 1_000; 7 / 2.0; 1 / 0.0",
+            "1 < 2; 2 <= 1; 1 > 2; 2 >= 2; 1 == 1.0; true != false; !5; !!true; !if (false) { 1 }",
+            r#""Hello, " + "World!"; "a" == "a""#,
+            "if (true) { 10 }",
+            "if (false) { 10 }",
+            "if (2 < 1) { 10 } else if (1 < 2) { 30 } else { 20 }",
+            "let x = if (1) { let y = 2; } else { 3 }; x",
+            "let a = 5; let b = a; let c = a + b + 5; c",
+            "let a = 1; let a = a + 1; a",
+            "let x = 1;",
+            "",
+            "{}",
+            "9; return 2 * 5; 9;",
+            "if (10 < 1) { 1 } else { if (1 < 10) { return 10; } return 1; }",
+            "let v = { return 5; 6 }; v + 1",
+            "let f = fn() { let v = { return 5; 6 }; 9 }; f()",
+            "let f = fn() { [1, { return 2; }, 3] }; f() + fn() { puts({ return 3; }); 4 }()",
+            "[1, 2 * 2, 3 + 3]; []",
+            "[1, 2, 3][-1] + [[1, 2], [3]][0][1]",
+            r#"let two = "two"; {"one": 10 - 9, two: 1 + 1, 4: 4, true: 5, false: 6}"#,
+            r#"{"foo": 5}["bar"]; {1: 1, 1: 2}[1]"#,
+            "let identity = fn(x) { return x; }; identity(5)",
+            "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5))",
+            "fn(x) { x; }(5)",
+            "fn() { }()",
+            "fn(x) { let y = x * 2; let z = y + 1; z }(4)",
+            "let x = 10; let f = fn(x) { let x = x + 1; x }; f(1) + x",
+            "let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(2)",
+            "let f = fn() { x }; let x = 5; f()",
+            "let c = fn(){ let x = 1; let h = fn(){ x }; let x = 2; h() }; c()",
+            "let c = fn(x) { let h = fn() { fn() { x } }; let x = x + 1; h()() }; c(1)",
+            "let twice = fn(f, x) { f(f(x)) }; let inc = fn(x) { x + 1 }; twice(inc, 5)",
+            "let compose = fn(f, g) { fn(x) { g(f(x)) } }; compose(fn(x) { x * 2 }, fn(x) { x + 1 })(5)",
+            "let a = fn(x) { fn(y) { fn(z) { x + y + z } } }; a(1)(2)(3)",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "let countdown = fn(n) { if (n == 0) { return 0; } countdown(n - 1) }; countdown(100)",
+            "let wrap = fn() { let inner = fn(n) { if (n == 0) { 0 } else { inner(n - 1) } }; inner(5) }; wrap()",
+            "let f = fn() { len }; let len = 1; puts(f())",
+            "len([1]); let len = fn(x) { 2 }; len([1])",
+            "let f = fn(){ let g = fn(){ h() }; let h = fn(){ 1 }; g() }; puts(f())",
+            "let x = 10; let f = fn() { let g = fn() { x }; let x = x + 1; g() }; f() + x",
+            "let f = fn() { if (true) { let g = fn() { y * 2 }; let y = 3; g() } }; f()",
+            "let map = fn(a, f) { if (len(a) == 0) { [] } else { [f(first(a))] + map(rest(a), f) } }; len(range(10))",
+            r#"let sum = fn(a) { if (len(a) == 0) { 0 } else { first(a) + sum(rest(a)) } }; sum(range(20)); push([1], 2); type(sum); str(1.5); keys({"a": 1})"#,
+            "let f = fn(x) { x }; f; len; type(len)",
+            "[fn(x) { x * 2 }][0](4)",
+            "puts(1)",
+            "5 + true; 5",
+            "-true",
+            "true + false",
+            "if (10 < 1) { 1 } else { true + false; }",
+            "foobar",
+            "10 / 0",
+            "5(1)",
+            "fn(x) { x }(1, 2)",
+            "len(1, 2)",
+            r#"int("abc")"#,
+            "[1, 2, 3][3]",
+            "1[0]",
+            r#"{"name": "Monkey"}[fn(x) { x }]"#,
+            "let f = fn() { 1 + foo };\nf()",
+            "let x = 1;\nlet y = x + true;",
+            "let f = fn(x) { x };\nf(1, 2)",
+            "let g = fn(n) { if (n == 0) { 1 / n } else { g(n - 1) } }; g(3)",
+        ];
+
+        for input in tests.iter() {
+            let expected = eval_input(input);
+            let result = run_input(input);
+            match (&expected, &result) {
+                (Object::Error(expected), Err(err)) => {
+                    assert_eq!(err, expected, "{}", input);
+                    assert_eq!(err.span, expected.span, "{}", input);
+                },
+                (expected, Ok(result)) => assert_eq!(result.inspect(), expected.inspect(), "{}", input),
+                _ => panic!("{}: expected {:?}, got {:?}", input, expected, result),
+            }
+        }
+    }
+
+    /// The unusable keys point at the whole literal, rather than at the key.
+    #[test]
+    pub fn test_hash_key_errors() {
+        let err = run_input("let h = {\n  1: 2, [1]: 2\n};").unwrap_err();
+        assert_eq!(err.message, "unusable as hash key: ARRAY");
+        assert_eq!(err.span, Some(Span::new(8, 18, 1, 9)));
+    }
+
+    #[test]
+    pub fn test_closures() {
+        match run_input("let f = fn(a, b) { fn() { a } }; f(1, 2)").unwrap() {
+            Object::Closure(closure) => {
+                assert_eq!(closure.free, vec![Rc::new(RefCell::new(Object::Int(1)))]);
+                assert!(closure.function.params.is_empty());
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn test_call_depth() {
+        let countdown = "let countdown = fn(n) { if (n == 0) { return 0; } countdown(n - 1) }; countdown(60000)";
+        assert_eq!(run_input(countdown), Ok(Object::Int(0)));
+
+        let err = run_input("let f = fn(n) { f(n + 1) + 1 };\nf(0)").unwrap_err();
+        assert_eq!(err.message, "stack overflow");
+        assert_eq!(err.span, Some(Span::new(16, 8, 1, 17)));
+    }
+}
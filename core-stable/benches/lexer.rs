@@ -0,0 +1,43 @@
+//! Lexes generated scripts of growing sizes. The lexer being linear, the
+//! throughput reported for each size should stay about the same.
+//!
+//! Run with `cargo bench --bench lexer`, and `--features logos` to compare
+//! with the lexer generated by logos.
+
+use criterion::{ black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput };
+
+use core_stable::lexer::Lexer;
+
+/// A script of `lines` lines, mixing every kind of token.
+fn generate_script(lines: usize) -> String {
+    let mut script = String::new();
+    for i in 0..lines {
+        script.push_str(&match i % 5 {
+            0 => format!("let value{} = fn(x, y) {{ if (x <= y) {{ x * {} }} else {{ y / 2.5e3 }} }};\n", i, i),
+            1 => format!("let list{} = [0x{:x}, 0b101, 1_000, \"item {}\\n\", true];\n", i, i, i),
+            2 => format!("let map{} = {{\"key\": list{}[1], \"é\": !false}};\n", i, i - 1),
+            3 => format!("puts(value{}(map{}[\"key\"], -{}) != {});\n", i - 3, i - 1, i, i),
+            _ => String::from("\t\n"),
+        });
+    }
+    script
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for lines in [1_250, 2_500, 5_000, 10_000].iter() {
+        let script = generate_script(*lines);
+        group.throughput(Throughput::Bytes(script.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &script, |b, script| {
+            b.iter(|| Lexer::new(black_box(script.clone())).count())
+        });
+        #[cfg(feature = "logos")]
+        group.bench_with_input(BenchmarkId::new("logos", lines), &script, |b, script| {
+            b.iter(|| core_stable::logos_lexer::LogosLexer::new(black_box(script)).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexer);
+criterion_main!(benches);
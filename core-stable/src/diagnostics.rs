@@ -0,0 +1,222 @@
+use std::fmt::Write;
+
+use super::span::Span;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+/// A problem found in the source, from the lexer, the parser or the evaluator.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Written next to the underlined source.
+    pub label: Option<String>,
+    /// Suggestion written after the source.
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, message.into(), span)
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, message.into(), span)
+    }
+
+    fn new(severity: Severity, message: String, span: Span) -> Self {
+        Self {
+            severity,
+            message,
+            span,
+            label: None,
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes diagnostics the way compilers do:
+///
+/// ```text
+/// error: expected `;`, found `x`
+///  --> script.mk:1:11
+///   |
+/// 1 | let y = 5 x
+///   |           ^ expected `;`
+///   |
+///   = help: add a `;` after the let statement
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Renderer {
+    colored: bool,
+}
+
+impl Renderer {
+    pub fn plain() -> Self {
+        Self { colored: false }
+    }
+
+    /// Uses ANSI escape codes, for terminals.
+    pub fn colored() -> Self {
+        Self { colored: true }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.colored {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            String::from(text)
+        }
+    }
+
+    /// Renders `diagnostic`, found in the file `file_name` whose content is `source`.
+    pub fn render(&self, diagnostic: &Diagnostic, file_name: &str, source: &str) -> String {
+        let span = diagnostic.span;
+        let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let bar = self.paint(BLUE, "|");
+        let color = diagnostic.severity.color();
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(color, diagnostic.severity.name()),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+        let _ = writeln!(out, "{}{} {}:{}:{}", gutter, self.paint(BLUE, "-->"), file_name, span.line, span.column);
+        let _ = writeln!(out, "{} {}", gutter, bar);
+        let _ = writeln!(out, "{} {} {}", self.paint(BLUE, &number), bar, line.trim_end());
+
+        // Keeps the tabulations of the line so that the carets are aligned.
+        let padding: String = line
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let start = line.char_indices().nth(span.column.saturating_sub(1)).map(|(i, _)| i).unwrap_or(line.len());
+        let underlined = line[start..].chars().scan(0, |len, ch| {
+            *len += ch.len_utf8();
+            if *len <= span.len { Some(ch) } else { None }
+        }).count();
+        let carets = "^".repeat(std::cmp::max(underlined, 1));
+        let mut marker = self.paint(color, &carets);
+        if let Some(label) = &diagnostic.label {
+            marker = format!("{} {}", marker, self.paint(color, label));
+        }
+        let _ = writeln!(out, "{} {} {}{}", gutter, bar, padding, marker);
+
+        if let Some(help) = &diagnostic.help {
+            let _ = writeln!(out, "{} {}", gutter, bar);
+            let _ = writeln!(out, "{} {} {}: {}", gutter, self.paint(BLUE, "="), self.paint(CYAN, "help"), help);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_render_plain() {
+        let source = "let x = 1;\nlet y = 5 x\n";
+        let diagnostic = Diagnostic::error("expected `;`, found `x`", Span::new(21, 1, 2, 11))
+            .with_label("expected `;`")
+            .with_help("add a `;` after the let statement");
+        assert_eq!(
+            Renderer::plain().render(&diagnostic, "script.mk", source),
+            "\
+error: expected `;`, found `x`
+ --> script.mk:2:11
+  |
+2 | let y = 5 x
+  |           ^ expected `;`
+  |
+  = help: add a `;` after the let statement
+"
+        );
+    }
+
+    #[test]
+    pub fn test_render_span_and_tabulations() {
+        let source = "\tfoo + bar";
+        let diagnostic = Diagnostic::warning("unused", Span::new(1, 9, 1, 2));
+        assert_eq!(
+            Renderer::plain().render(&diagnostic, "<stdin>", source),
+            "\
+warning: unused
+ --> <stdin>:1:2
+  |
+1 | \tfoo + bar
+  | \t^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    pub fn test_render_end_of_input() {
+        let diagnostic = Diagnostic::error("expected `}`, found end of input", Span::new(5, 0, 1, 6));
+        assert_eq!(
+            Renderer::plain().render(&diagnostic, "a.mk", "{ 1; "),
+            "\
+error: expected `}`, found end of input
+ --> a.mk:1:6
+  |
+1 | { 1;
+  |      ^
+"
+        );
+    }
+
+    #[test]
+    pub fn test_render_colored() {
+        let diagnostic = Diagnostic::error("oops", Span::new(0, 1, 1, 1));
+        let rendered = Renderer::colored().render(&diagnostic, "a.mk", "x");
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: oops\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
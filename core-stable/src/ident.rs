@@ -0,0 +1,104 @@
+//! The identifiers follow Unicode UAX #31: they start with a `XID_Start`
+//! character or `_`, go on with `XID_Continue` characters, and are compared
+//! in NFC, so that `é` is the same name whether it is written with one code
+//! point or with `e` and a combining accent.
+
+use unicode_normalization::{ is_nfc_quick, IsNormalized, UnicodeNormalization };
+use unicode_script::{ Script, UnicodeScript };
+use unicode_security::{ is_potential_mixed_script_confusable_char, MixedScript };
+
+use super::diagnostics::Diagnostic;
+use super::span::Span;
+
+/// Whether an identifier may start with `ch`.
+pub fn is_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+/// Whether an identifier may go on with `ch`.
+pub fn is_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
+}
+
+/// The NFC form of `ident`, under which identifiers are compared.
+pub fn normalize(ident: &str) -> String {
+    match is_nfc_quick(ident.chars()) {
+        IsNormalized::Yes => String::from(ident),
+        _ => ident.nfc().collect(),
+    }
+}
+
+/// A warning if `ident`, found at `span`, mixes scripts and all its
+/// characters of a script other than Latin could be mistaken for characters
+/// of another script, like the Cyrillic `а` in `pаypal`. Scripts used
+/// together, like Han and Hiragana, are not mixed.
+pub fn mixed_script_warning(ident: &str, span: Span) -> Option<Diagnostic> {
+    if ident.is_single_script() {
+        return None;
+    }
+    let mut scripts: Vec<Script> = Vec::new();
+    for ch in ident.chars() {
+        let script = ch.script();
+        if !matches!(script, Script::Common | Script::Inherited | Script::Unknown) && !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+    let confusable = scripts.iter().filter(|script| **script != Script::Latin).find_map(|script| {
+        let mut chars = ident.chars().filter(|ch| ch.script() == *script);
+        let first = chars.next()?;
+        let confusable = is_potential_mixed_script_confusable_char(first) && chars.all(is_potential_mixed_script_confusable_char);
+        if confusable { Some(first) } else { None }
+    })?;
+    let names: Vec<&str> = scripts.iter().map(|script| script.full_name()).collect();
+    let names = match names.split_last() {
+        Some((last, [])) => String::from(*last),
+        Some((last, others)) => format!("{} and {}", others.join(", "), last),
+        None => String::new(),
+    };
+    let diagnostic = Diagnostic::warning(format!("the identifier `{}` mixes the {} scripts", ident, names), span)
+        .with_label(format!("`{}` is {}", confusable, confusable.script().full_name()))
+        .with_help("characters of different scripts may look the same, write the identifier in a single script");
+    Some(diagnostic)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_identifier_characters() {
+        for ch in ['a', 'Z', '_', 'é', 'λ', 'д', '名', 'ア'].iter() {
+            assert!(is_start(*ch), "{}", ch);
+            assert!(is_continue(*ch), "{}", ch);
+        }
+        for ch in ['0', '٣', '\u{301}'].iter() {
+            assert!(!is_start(*ch), "{}", ch);
+            assert!(is_continue(*ch), "{}", ch);
+        }
+        for ch in ['-', ' ', '$', '€', '😀'].iter() {
+            assert!(!is_start(*ch), "{}", ch);
+            assert!(!is_continue(*ch), "{}", ch);
+        }
+    }
+
+    #[test]
+    pub fn test_normalize() {
+        assert_eq!(normalize("caf\u{65}\u{301}"), "caf\u{e9}");
+        assert_eq!(normalize("caf\u{e9}"), "caf\u{e9}");
+        assert_eq!(normalize("snake_case"), "snake_case");
+    }
+
+    #[test]
+    pub fn test_mixed_script_warning() {
+        let span = Span::new(4, 7, 1, 5);
+        for ident in ["paypal", "été", "λx", "x٣", "名前の", "данные", "_"].iter() {
+            assert_eq!(mixed_script_warning(ident, span), None, "{}", ident);
+        }
+
+        let warning = mixed_script_warning("p\u{430}ypal", span).unwrap();
+        assert!(!warning.is_error());
+        assert_eq!(warning.message, "the identifier `p\u{430}ypal` mixes the Latin and Cyrillic scripts");
+        assert_eq!(warning.label, Some(String::from("`\u{430}` is Cyrillic")));
+        assert_eq!(warning.span, span);
+    }
+}
@@ -1,11 +1,37 @@
+use std::borrow::Cow;
+use std::io::{ self, BufRead };
+
 use super::token;
+use super::ident;
+use super::span::Span;
+use super::diagnostics::Diagnostic;
 
-pub struct Lexer {
-	input: String,
-	current: usize
+/// Reads the tokens of a program. The lexer walks its input once, keeping
+/// the byte offset, line and column of the current character, so peeking
+/// and spanning a token take constant time whatever the size of the input.
+///
+/// The input is either a whole string, owned or borrowed, or read line by
+/// line from `Src` as the tokens are needed: only the lines of the current
+/// token are kept.
+pub struct Lexer<'a, Src = io::Empty> {
+	src: Src,
+	/// The input read so far from `src`, from byte offset `base` on.
+	buffer: Cow<'a, str>,
+	base: usize,
+	/// Byte offset of the first character of the token being read, which
+	/// must stay in `buffer`.
+	token_start: usize,
+	/// Whether `src` reached its end, or failed.
+	exhausted: bool,
+	/// Byte offset of the current character.
+	offset: usize,
+	line: usize,
+	column: usize,
+	diagnostics: Vec<Diagnostic>,
 }
 
-enum IntPrefix {
+/// The base of an integer literal, given by its prefix.
+pub enum IntPrefix {
 	Hexadecimal,
 	Binary,
 	Octal,
@@ -15,20 +41,45 @@ enum IntPrefix {
 impl IntPrefix {
 	pub fn is_digit(&self, digit: char) -> bool {
 		match self {
-			Self::Hexadecimal => digit.is_digit(16),
+			Self::Hexadecimal => digit.is_ascii_hexdigit(),
 			Self::Binary => digit.is_digit(2),
 			Self::Octal => digit.is_digit(8),
-			Self::None => digit.is_digit(10)
+			Self::None => digit.is_ascii_digit()
 		}
 	}
 
-	pub fn parse_number(&self, number: &str) -> isize {
+	pub fn parse_number(&self, number: &str) -> Result<isize, String> {
+		if number.is_empty() {
+			return Err(format!("missing digits after `{}`", self.text()));
+		}
 		isize::from_str_radix(number, match self {
 			Self::None => 10,
 			Self::Hexadecimal => 16,
 			Self::Binary => 2,
 			Self::Octal => 8,
-		}).unwrap()
+		}).map_err(|_| String::from("integer literal is too large"))
+	}
+
+	/// The token of the number literal of digits `number`, without the
+	/// prefix and the `_` separators.
+	pub fn parse_literal(&self, number: &str, float: bool) -> Result<token::Token, String> {
+		if float {
+			match number.parse::<f64>() {
+				Ok(value) if value.is_finite() => Ok(token::Float(value)),
+				_ => Err(String::from("float literal is too large")),
+			}
+		} else {
+			self.parse_number(number).map(token::Int)
+		}
+	}
+
+	pub fn text(&self) -> &'static str {
+		match self {
+			Self::Hexadecimal => "0x",
+			Self::Binary => "0b",
+			Self::Octal => "0o",
+			Self::None => "",
+		}
 	}
 }
 
@@ -42,167 +93,524 @@ impl From<&str> for IntPrefix {
 		}
 	}
 }
-impl Lexer {
+impl Lexer<'static> {
 	pub fn new(input: String) -> Self {
+		Lexer::from_input(Cow::Owned(input))
+	}
+}
+
+impl<'a> Lexer<'a> {
+	/// A lexer reading `input` in place, without copying it.
+	pub fn borrowed(input: &'a str) -> Self {
+		Lexer::from_input(Cow::Borrowed(input))
+	}
+
+	fn from_input(input: Cow<'a, str>) -> Self {
 		Lexer {
-			input: input,
-			current: 0,
+			src: io::empty(),
+			buffer: input,
+			base: 0,
+			token_start: 0,
+			exhausted: true,
+			offset: 0,
+			line: 1,
+			column: 1,
+			diagnostics: Vec::new(),
 		}
 	}
+}
 
-	fn get_char(&self, distance_from_current: isize) -> Option<char> {
-		self.input.chars().nth((self.current as isize + distance_from_current) as usize)
+impl<'a, Src: BufRead> Lexer<'a, Src> {
+	/// A lexer reading its input from `src` incrementally. Failing to read
+	/// `src`, or reading invalid UTF-8, is reported as a diagnostic and ends
+	/// the input.
+	pub fn from_reader(src: Src) -> Self {
+		Lexer {
+			src,
+			buffer: Cow::Owned(String::new()),
+			base: 0,
+			token_start: 0,
+			exhausted: false,
+			offset: 0,
+			line: 1,
+			column: 1,
+			diagnostics: Vec::new(),
+		}
 	}
 
-	pub fn read_char(&mut self) {
-		match self.input.chars().nth(1) {
-			Some(_) => self.current += 1,
-			None => return ()
+	/// Reads the next line of `src` into the buffer, first dropping what is
+	/// before the current token. Returns `false` at the end of `src`.
+	fn fill(&mut self) -> bool {
+		if self.exhausted {
+			return false;
+		}
+		self.buffer.to_mut().drain(..self.token_start - self.base);
+		self.base = self.token_start;
+		match self.src.read_line(self.buffer.to_mut()) {
+			Ok(0) => self.exhausted = true,
+			Ok(_) => (),
+			Err(err) => {
+				self.exhausted = true;
+				let span = self.here();
+				self.diagnostics.push(Diagnostic::error(format!("cannot read the input: {}", err), span));
+			}
+		}
+		!self.exhausted
+	}
+
+	/// The input from byte offset `start` up to byte offset `end`, excluded.
+	fn slice(&self, start: usize, end: usize) -> &str {
+		&self.buffer[start - self.base..end - self.base]
+	}
+
+	/// Empty span at the current character.
+	fn here(&self) -> Span {
+		Span::new(self.offset, 0, self.line, self.column)
+	}
+
+	/// Span of the characters from `start` up to the current one, excluded.
+	fn span_from(&self, start: Span) -> Span {
+		Span { len: self.offset - start.offset, ..start }
+	}
+
+	/// Empty span at the end of the input.
+	pub fn eof_span(&mut self) -> Span {
+		self.token_start = self.offset;
+		while self.fill() {}
+		let (mut line, mut column) = (self.line, self.column);
+		for ch in self.slice(self.offset, self.base + self.buffer.len()).chars() {
+			if ch == '\n' {
+				line += 1;
+				column = 1;
+			} else {
+				column += 1;
+			}
+		}
+		Span::new(self.base + self.buffer.len(), 0, line, column)
+	}
+
+	/// Next token, with the span of the source it comes from.
+	pub fn next_spanned(&mut self) -> Option<(token::Token, Span)> {
+		self.next_token(true)
+	}
+
+	/// Same as `next_spanned`, but the identifiers, strings and doc comments
+	/// are left empty rather than copied, for the callers which slice their
+	/// text out of the input by their span, like `tokens::tokenize`.
+	pub fn next_kind_spanned(&mut self) -> Option<(token::Token, Span)> {
+		self.next_token(false)
+	}
+
+	fn next_token(&mut self, copy_text: bool) -> Option<(token::Token, Span)> {
+		loop {
+			self.skip_whitespaces();
+			self.token_start = self.offset;
+			if !self.skip_comment() {
+				break;
+			}
+		}
+		let start = self.here();
+		let reported = self.diagnostics.len();
+		let tok = self.read_token(copy_text);
+		let span = self.span_from(start);
+		match tok {
+			token::EndOfFile => None,
+			// Already reported while reading the token.
+			token::Illegal(_) if self.diagnostics.len() > reported => Some((tok, span)),
+			token::Illegal(ch) => {
+				self.diagnostics.push(
+					Diagnostic::error(format!("illegal character `{}`", ch), span)
+						.with_label("not part of the language")
+				);
+				Some((tok, span))
+			},
+			tok => Some((tok, span)),
+		}
+	}
+
+	/// Problems found in the tokens read so far.
+	pub fn diagnostics(&self) -> &[Diagnostic] {
+		&self.diagnostics
+	}
+
+	/// The character `distance` characters after the current one. The
+	/// lookahead is at most a few characters, so this does not depend on the
+	/// size of the input.
+	fn peek_char(&mut self, distance: usize) -> Option<char> {
+		loop {
+			if let Some(ch) = self.slice(self.offset, self.base + self.buffer.len()).chars().nth(distance) {
+				return Some(ch);
+			}
+			if !self.fill() {
+				return None;
+			}
+		}
+	}
+
+	/// Moves past the current character, returning it.
+	pub fn read_char(&mut self) -> Option<char> {
+		let ch = self.peek_char(0)?;
+		self.offset += ch.len_utf8();
+		if ch == '\n' {
+			self.line += 1;
+			self.column = 1;
+		} else {
+			self.column += 1;
+		}
+		Some(ch)
+	}
+
+	/// Moves past the current character if it is `expected`.
+	fn eat_char(&mut self, expected: char) -> bool {
+		if self.peek_char(0) == Some(expected) {
+			self.read_char();
+			true
+		} else {
+			false
 		}
 	}
 
 	pub fn skip_whitespaces(&mut self) {
-		while matches!{ self.get_char(0), Some(' ') | Some('\t') | Some('\n') | Some('\r') } {
+		while matches!{ self.peek_char(0), Some(' ') | Some('\t') | Some('\n') | Some('\r') } {
 			self.read_char();
 		}
 	}
 
-	pub fn read_identifier(&mut self) -> String {
-		let mut buf = String::new();
-		while match self.get_char(0) {
-			Some(ch) => ch.is_alphanumeric(),
-			None => false
-		} {
-			buf = format!("{}{}", buf, match self.get_char(0) {
-				Some(ch) => ch,
-				None => panic!("Corrupted data!")
-			});
-			self.read_char()
-        }
-        self.current -= 1;
-		buf
-	}
-	
-	pub fn read_number(&mut self) -> isize {
-		let mut buf: String = String::new();
-		let prefix = match IntPrefix::from(&self.input[self.current..self.current+2]) {
-			IntPrefix::None => IntPrefix::None,
-			prefix => {
-				self.current += 2;
-				prefix
+	/// Skips the `//` or `/* */` comment starting at the current character,
+	/// if any. The `///` doc comments are tokens, and are not skipped.
+	fn skip_comment(&mut self) -> bool {
+		if self.peek_char(0) != Some('/') {
+			return false;
+		}
+		match (self.peek_char(0), self.peek_char(1)) {
+			(Some('/'), Some('/')) if !self.at_doc_comment() => {
+				while !matches!(self.peek_char(0), Some('\n') | None) {
+					self.read_char();
+				}
+				true
+			},
+			(Some('/'), Some('*')) => {
+				self.skip_block_comment();
+				true
+			},
+			_ => false
+		}
+	}
+
+	/// Skips the block comment starting at the current character, along
+	/// with the block comments nested in it.
+	fn skip_block_comment(&mut self) {
+		let start = self.here();
+		self.read_char();
+		self.read_char();
+		let mut depth = 1;
+		while depth > 0 {
+			// Nothing of the comment is kept, so the lines already read are dropped.
+			self.token_start = self.offset;
+			match (self.read_char(), self.peek_char(0)) {
+				(Some('/'), Some('*')) => {
+					self.read_char();
+					depth += 1;
+				},
+				(Some('*'), Some('/')) => {
+					self.read_char();
+					depth -= 1;
+				},
+				(Some(_), _) => (),
+				(None, _) => {
+					self.diagnostics.push(
+						Diagnostic::error("unterminated block comment", Span { len: 2, ..start })
+							.with_label("the comment starts here")
+							.with_help("add a `*/` at the end of the comment")
+					);
+					return;
+				}
 			}
-		};
-		while match self.get_char(0) {
-			Some(ch) => prefix.is_digit(ch),
-			None => false
-		} {
-			match self.get_char(0) {
-				Some(ch) => buf.push_str(ch.to_string().as_str()),
-				_ => {}
-			};
+		}
+	}
+
+	/// Whether the current character starts a `///` comment. Four slashes or
+	/// more make a regular comment.
+	fn at_doc_comment(&mut self) -> bool {
+		self.peek_char(0) == Some('/')
+			&& self.peek_char(1) == Some('/')
+			&& self.peek_char(2) == Some('/')
+			&& self.peek_char(3) != Some('/')
+	}
+
+	/// Reads the `///` comment starting at the current character, up to the
+	/// end of its line.
+	fn read_doc_comment(&mut self, copy_text: bool) -> token::Token {
+		for _ in 0..3 {
+			self.read_char();
+		}
+		let start = self.offset;
+		while !matches!((self.peek_char(0), self.peek_char(1)), (Some('\n'), _) | (Some('\r'), Some('\n')) | (None, _)) {
 			self.read_char();
 		}
-		self.current -= 1;
-		prefix.parse_number(&buf)
+		token::DocComment(if copy_text { String::from(self.slice(start, self.offset)) } else { String::new() })
 	}
 
-	/*	pub fn read_string(&mut self) -> String {
+	/// Reads the identifier starting at the current character, and returns
+	/// its NFC form.
+	pub fn read_identifier(&mut self) -> String {
+		let start = self.offset;
+		self.skip_identifier();
+		ident::normalize(self.slice(start, self.offset))
+	}
 
-	}*/
-}
+	fn skip_identifier(&mut self) {
+		while matches!(self.peek_char(0), Some(ch) if ident::is_continue(ch)) {
+			self.read_char();
+		}
+	}
 
-impl Iterator for Lexer {
-	type Item = token::Token;
-	fn next(&mut self) -> Option<Self::Item> {
-		self.skip_whitespaces();
-		let tok = match self.get_char(0) {
-			//check the equality or assignment case
-			Some('=') => {
-				//which one it  is? equality or assignment?
-				match self.get_char(1) {
-					Some('=') => {
-                        self.current += 1;
-						token::Eq
+	/// Reads a number literal: an integer, possibly prefixed by `0x`, `0b` or
+	/// `0o`, or a decimal float with a fraction and/or an exponent. The digits
+	/// may be separated by `_`.
+	pub fn read_number(&mut self) -> token::Token {
+		let start = self.here();
+		let first = self.peek_char(0).unwrap_or('0');
+		let prefix = match (self.peek_char(0), self.peek_char(1)) {
+			(Some('0'), Some('x')) => IntPrefix::Hexadecimal,
+			(Some('0'), Some('b')) => IntPrefix::Binary,
+			(Some('0'), Some('o')) => IntPrefix::Octal,
+			_ => IntPrefix::None,
+		};
+		if !matches!(prefix, IntPrefix::None) {
+			self.read_char();
+			self.read_char();
+		}
+		let digits = self.offset;
+		self.skip_digits(&prefix);
+		let mut float = false;
+		if let IntPrefix::None = prefix {
+			if self.peek_char(0) == Some('.') && matches!(self.peek_char(1), Some(ch) if ch.is_ascii_digit()) {
+				float = true;
+				self.read_char();
+				self.skip_digits(&prefix);
+			}
+			if let Some('e') | Some('E') = self.peek_char(0) {
+				let signed = matches!(self.peek_char(1), Some('+') | Some('-'));
+				let digit = if signed { 2 } else { 1 };
+				if matches!(self.peek_char(digit), Some(ch) if ch.is_ascii_digit()) {
+					float = true;
+					self.read_char();
+					if signed {
+						self.read_char();
 					}
-					_ => token::Assign
+					self.skip_digits(&prefix);
 				}
 			}
-			
-			Some('+') => token::Plus,
-
-			Some('-') => token::Minus,
+		}
 
-			Some('!') => match self.get_char(1) {
-                Some('=') => {
-					self.current += 1;
-					token::NotEq
-				},
-                _ => token::Bang
-            }
+		// The literal is copied only to drop its `_` separators.
+		let literal = match self.slice(digits, self.offset) {
+			number if number.contains('_') => prefix.parse_literal(&number.replace('_', ""), float),
+			number => prefix.parse_literal(number, float),
+		};
+		literal.unwrap_or_else(|message| {
+			let span = self.span_from(start);
+			self.diagnostics.push(Diagnostic::error(message, span));
+			token::Illegal(first)
+		})
+	}
 
-			Some('/') => token::Slash,
+	/// Moves past the digits of `prefix` from the current character, and
+	/// their `_` separators.
+	fn skip_digits(&mut self, prefix: &IntPrefix) {
+		while matches!(self.peek_char(0), Some(ch) if prefix.is_digit(ch) || ch == '_') {
+			self.read_char();
+		}
+	}
 
-			Some('*') => token::Asterisk,
+	/// Reads the string literal whose opening quote is the current character,
+	/// up to its closing quote. Returns `None`, after reporting why, if the
+	/// literal is unterminated or has invalid escape sequences.
+	pub fn read_string(&mut self) -> Option<String> {
+		let mut buf = String::new();
+		if self.scan_string(Some(&mut buf)) { Some(buf) } else { None }
+	}
 
-			Some('<') => match self.get_char(1) {
-				Some('=') => {
-					self.current += 1;
-					token::LowerThanOrEqualTo
+	/// Same as `read_string`, but only pushes the characters of the string
+	/// to `buf` if given, returning whether the literal is valid.
+	fn scan_string(&mut self, mut buf: Option<&mut String>) -> bool {
+		let start = self.here();
+		self.read_char();
+		let mut valid = true;
+		loop {
+			match self.peek_char(0) {
+				Some('"') => break,
+				Some('\\') => {
+					let escape = self.here();
+					match self.read_escape() {
+						Some(ch) => buf.iter_mut().for_each(|buf| buf.push(ch)),
+						None => {
+							valid = false;
+							let span = self.span_from(escape);
+							let text = self.slice(escape.offset, self.offset);
+							self.diagnostics.push(
+								Diagnostic::error(format!("invalid escape sequence `{}`", text), span)
+									.with_help("the escape sequences are `\\n`, `\\r`, `\\t`, `\\0`, `\\\"`, `\\\\` and `\\u{...}`")
+							);
+						}
+					}
 				},
-				_ => token::LowerThan
-			},
-
-			Some('>') => match self.get_char(1) {
-				Some('=') => {
-					self.current += 1;
-					token::GreaterThanOrEqualTo
+				Some(ch) => {
+					buf.iter_mut().for_each(|buf| buf.push(ch));
+					self.read_char();
 				},
-				_ => token::LowerThan
+				None => {
+					let span = Span { len: 1, ..start };
+					self.diagnostics.push(
+						Diagnostic::error("unterminated string", span)
+							.with_label("the string starts here")
+							.with_help("add a `\"` at the end of the string")
+					);
+					return false;
+				}
+			}
+		}
+		self.read_char();
+		valid
+	}
+
+	/// Reads the escape sequence whose `\` is the current character, up to
+	/// its last character.
+	fn read_escape(&mut self) -> Option<char> {
+		self.read_char();
+		match self.read_char()? {
+			'n' => Some('\n'),
+			'r' => Some('\r'),
+			't' => Some('\t'),
+			'0' => Some('\0'),
+			'"' => Some('"'),
+			'\\' => Some('\\'),
+			'u' if self.eat_char('{') => {
+				let start = self.offset;
+				while matches!(self.peek_char(0), Some(ch) if ch.is_ascii_hexdigit()) {
+					self.read_char();
+				}
+				let end = self.offset;
+				if !self.eat_char('}') || end == start || end - start > 6 {
+					return None;
+				}
+				char::from_u32(u32::from_str_radix(self.slice(start, end), 16).ok()?)
 			},
+			_ => None
+		}
+	}
 
-			Some(';') => token::Semicolon,
+	/// Reads the next token, copying the text of the identifiers, strings
+	/// and doc comments only if `copy_text` is set.
+	fn read_token(&mut self, copy_text: bool) -> token::Token {
+		let ch = match self.peek_char(0) {
+			Some(ch) => ch,
+			None => return token::EndOfFile
+		};
+		if ch == '"' {
+			let mut string = String::new();
+			let valid = self.scan_string(if copy_text { Some(&mut string) } else { None });
+			return if valid { token::String(string) } else { token::Illegal('"') };
+		}
+		if ident::is_start(ch) {
+			let start = self.here();
+			self.skip_identifier();
+			let span = self.span_from(start);
+			let text = self.slice(start.offset, self.offset);
+			let warning = ident::mixed_script_warning(text, span);
+			// The keywords are ASCII, hence in NFC already.
+			let tok = match token::lookup_keyword(text) {
+				Some(keyword) => keyword,
+				None if copy_text => token::Ident(ident::normalize(text)),
+				None => token::Ident(String::new()),
+			};
+			self.diagnostics.extend(warning);
+			return tok;
+		}
+		if ch.is_ascii_digit() {
+			return self.read_number();
+		}
+		if ch == '/' && self.at_doc_comment() {
+			return self.read_doc_comment(copy_text);
+		}
 
-			Some(',') => token::Comma,
+		self.read_char();
+		// The longest operator starting here wins, so `**` is a single token
+		// rather than two `*`, and `<<=` is `<<` then `=`.
+		match ch {
+			//check the equality or assignment case
+			'=' => if self.eat_char('=') { token::Eq } else if self.eat_char('>') { token::FatArrow } else { token::Assign },
 
-			Some('{') => token::LeftBrace,
+			'+' => if self.eat_char('=') { token::PlusAssign } else { token::Plus },
 
-			Some('}') => token::RightBrace,
+			'-' => if self.eat_char('=') { token::MinusAssign } else if self.eat_char('>') { token::Arrow } else { token::Minus },
 
-			Some('(') => token::LeftParen,
+			'!' => if self.eat_char('=') { token::NotEq } else { token::Bang },
 
-			Some(')') => token::RightParen,
+			'/' => if self.eat_char('=') { token::SlashAssign } else { token::Slash },
 
-			None => token::EndOfFile,
+			'*' => if self.eat_char('*') { token::Power } else if self.eat_char('=') { token::AsteriskAssign } else { token::Asterisk },
 
-			Some(ch) => {
-				if ch.is_alphabetic() {
-					let ident = self.read_identifier();
-					token::lookup_indent(ident.as_str())
-				} else if ch.is_digit(10) {
-					let num = self.read_number();
-					token::Int(num)
-				} else {
-					token::Illegal(ch)
-                }
-            }
-        };
-		self.read_char();
-		match tok {
-			tok if tok == token::EndOfFile => None,
-			tok => Some(tok),
+			'%' => token::Percent,
+
+			'&' => if self.eat_char('&') { token::And } else { token::Ampersand },
+
+			'|' => if self.eat_char('|') { token::Or } else { token::Pipe },
+
+			'^' => token::Caret,
+
+			'~' => token::Tilde,
+
+			'<' => if self.eat_char('<') { token::ShiftLeft } else if self.eat_char('=') { token::LowerThanOrEqualTo } else { token::LowerThan },
+
+			'>' => if self.eat_char('>') { token::ShiftRight } else if self.eat_char('=') { token::GreaterThanOrEqualTo } else { token::GreaterThan },
+
+			'.' => if self.eat_char('.') { token::DotDot } else { token::Dot },
+
+			';' => token::Semicolon,
+
+			',' => token::Comma,
+
+			':' => token::Colon,
+
+			'{' => token::LeftBrace,
+
+			'}' => token::RightBrace,
+
+			'(' => token::LeftParen,
+
+			')' => token::RightParen,
+
+			'[' => token::LeftBracket,
+
+			']' => token::RightBracket,
+
+			ch => token::Illegal(ch)
 		}
 	}
 }
 
+impl<'a, Src: BufRead> Iterator for Lexer<'a, Src> {
+	type Item = token::Token;
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_spanned().map(|(tok, _)| tok)
+	}
+}
+
 #[cfg(test)]
 pub mod test {
     use super::token;
+    use super::Span;
+    use super::Diagnostic;
     #[derive(Debug)]
     struct ExpectedToken(token::Token);
 
+    pub const NEXT_TOKEN_INPUT: &str = "let five = 5;\n\nlet ten = 10;\n\nlet add = fn(x, y) {\n\tx + y;\n};\n\nlet result = add(five, ten);\n!-/ *5; let True = true; let False = !True;";
+
     #[test]
     pub fn test_next_token() {
-        let input = String::from("let five = 5;\n\nlet ten = 10;\n\nlet add = fn(x, y) {\n\tx + y;\n};\n\nlet result = add(five, ten);\n!-/*5; let True = true; let False = !True");
+        let input = String::from(NEXT_TOKEN_INPUT);
         let tests = vec![
             //begin 1
             ExpectedToken(token::Let),
@@ -301,5 +709,369 @@ pub mod test {
             assert_eq!(test_tok.0, tok);
         }
         println!("}}");
+        assert!(tests_iter.next().is_none(), "missing tokens");
+    }
+
+    #[test]
+    pub fn test_spans() {
+        let input = String::from("let é = 10;\n  x >= \n\t7");
+        let expected = vec![
+            (token::Let, Span::new(0, 3, 1, 1)),
+            (token::Ident(String::from("é")), Span::new(4, 2, 1, 5)),
+            (token::Assign, Span::new(7, 1, 1, 7)),
+            (token::Int(10), Span::new(9, 2, 1, 9)),
+            (token::Semicolon, Span::new(11, 1, 1, 11)),
+            (token::Ident(String::from("x")), Span::new(15, 1, 2, 3)),
+            (token::GreaterThanOrEqualTo, Span::new(17, 2, 2, 5)),
+            (token::Int(7), Span::new(22, 1, 3, 2)),
+        ];
+
+        let mut lex = crate::lexer::Lexer::new(input);
+        for expected in expected {
+            assert_eq!(lex.next_spanned(), Some(expected));
+        }
+        assert_eq!(lex.next_spanned(), None);
+        assert_eq!(lex.eof_span(), Span::new(23, 0, 3, 3));
+    }
+
+    #[test]
+    pub fn test_illegal_character_diagnostics() {
+        let mut lex = crate::lexer::Lexer::new(String::from("1 # 2"));
+        let tokens: Vec<token::Token> = lex.by_ref().collect();
+        assert_eq!(tokens, vec![token::Int(1), token::Illegal('#'), token::Int(2)]);
+        assert_eq!(
+            lex.diagnostics(),
+            &[Diagnostic::error("illegal character `#`", Span::new(2, 1, 1, 3)).with_label("not part of the language")]
+        );
+    }
+
+    #[test]
+    pub fn test_strings() {
+        let tests = [
+            (r#""hello""#, "hello"),
+            (r#""""#, ""),
+            (r#""a\nb\tc\r\0""#, "a\nb\tc\r\0"),
+            (r#""say \"hi\" \\o/""#, "say \"hi\" \\o/"),
+            (r#""\u{e9}t\u{E9} \u{1F600}""#, "été 😀"),
+            ("\"multi\nline\"", "multi\nline"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            assert_eq!(lex.next(), Some(token::String(String::from(*expected))), "{}", input);
+            assert_eq!(lex.next(), None);
+            assert!(lex.diagnostics().is_empty());
+        }
+
+        let mut lex = crate::lexer::Lexer::new(String::from(r#"let s = "é";"#));
+        lex.next();
+        lex.next();
+        lex.next();
+        assert_eq!(lex.next_spanned(), Some((token::String(String::from("é")), Span::new(8, 4, 1, 9))));
+        assert_eq!(lex.next(), Some(token::Semicolon));
+    }
+
+    #[test]
+    pub fn test_string_errors() {
+        let tests = [
+            (
+                "let s = \"abc;",
+                vec![token::Let, token::Ident(String::from("s")), token::Assign, token::Illegal('"')],
+                vec![Diagnostic::error("unterminated string", Span::new(8, 1, 1, 9))
+                    .with_label("the string starts here")
+                    .with_help("add a `\"` at the end of the string")],
+            ),
+            (
+                r#""a\qb" 1"#,
+                vec![token::Illegal('"'), token::Int(1)],
+                vec![Diagnostic::error(r"invalid escape sequence `\q`", Span::new(2, 2, 1, 3))
+                    .with_help(r#"the escape sequences are `\n`, `\r`, `\t`, `\0`, `\"`, `\\` and `\u{...}`"#)],
+            ),
+            (
+                r#""\u{110000}" "\u{}""#,
+                vec![token::Illegal('"'), token::Illegal('"')],
+                vec![
+                    Diagnostic::error(r"invalid escape sequence `\u{110000}`", Span::new(1, 10, 1, 2))
+                        .with_help(r#"the escape sequences are `\n`, `\r`, `\t`, `\0`, `\"`, `\\` and `\u{...}`"#),
+                    Diagnostic::error(r"invalid escape sequence `\u{}`", Span::new(14, 4, 1, 15))
+                        .with_help(r#"the escape sequences are `\n`, `\r`, `\t`, `\0`, `\"`, `\\` and `\u{...}`"#),
+                ],
+            ),
+        ];
+
+        for (input, tokens, diagnostics) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            let found: Vec<token::Token> = lex.by_ref().collect();
+            assert_eq!(&found, tokens, "{}", input);
+            assert_eq!(lex.diagnostics(), diagnostics.as_slice(), "{}", input);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_numbers() {
+        let tests = [
+            ("42", token::Int(42)),
+            ("1_000_000", token::Int(1_000_000)),
+            ("0xff_ff", token::Int(0xffff)),
+            ("0b1010", token::Int(10)),
+            ("0o17", token::Int(15)),
+            ("2.75", token::Float(2.75)),
+            ("1e-9", token::Float(1e-9)),
+            ("2.5E3", token::Float(2500.0)),
+            ("6.02e+23", token::Float(6.02e23)),
+            ("1_000.000_1", token::Float(1000.0001)),
+            ("1e3", token::Float(1000.0)),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            assert_eq!(lex.next(), Some(expected.clone()), "{}", input);
+            assert_eq!(lex.next(), None, "{}", input);
+        }
+
+        let lex = crate::lexer::Lexer::new(String::from("1.foo 2e 3.5.6"));
+        assert_eq!(lex.collect::<Vec<token::Token>>(), vec![
+            token::Int(1),
+            token::Dot,
+            token::Ident(String::from("foo")),
+            token::Int(2),
+            token::Ident(String::from("e")),
+            token::Float(3.5),
+            token::Dot,
+            token::Int(6),
+        ]);
+    }
+
+    #[test]
+    pub fn test_number_errors() {
+        let tests = [
+            ("99999999999999999999", "integer literal is too large", Span::new(0, 20, 1, 1)),
+            ("x = 0x;", "missing digits after `0x`", Span::new(4, 2, 1, 5)),
+            ("1e999", "float literal is too large", Span::new(0, 5, 1, 1)),
+        ];
+
+        for (input, message, span) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            assert!(lex.by_ref().any(|tok| matches!(tok, token::Illegal(_))), "{}", input);
+            assert_eq!(lex.diagnostics(), &[Diagnostic::error(*message, *span)]);
+        }
+    }
+
+    #[test]
+    pub fn test_operators() {
+        let tests = [
+            ("=", token::Assign),
+            ("==", token::Eq),
+            ("=>", token::FatArrow),
+            ("+", token::Plus),
+            ("+=", token::PlusAssign),
+            ("-", token::Minus),
+            ("-=", token::MinusAssign),
+            ("->", token::Arrow),
+            ("*", token::Asterisk),
+            ("*=", token::AsteriskAssign),
+            ("**", token::Power),
+            ("/", token::Slash),
+            ("/=", token::SlashAssign),
+            ("%", token::Percent),
+            ("!", token::Bang),
+            ("!=", token::NotEq),
+            ("<", token::LowerThan),
+            ("<=", token::LowerThanOrEqualTo),
+            ("<<", token::ShiftLeft),
+            (">", token::GreaterThan),
+            (">=", token::GreaterThanOrEqualTo),
+            (">>", token::ShiftRight),
+            ("&", token::Ampersand),
+            ("&&", token::And),
+            ("|", token::Pipe),
+            ("||", token::Or),
+            ("^", token::Caret),
+            ("~", token::Tilde),
+            (".", token::Dot),
+            ("..", token::DotDot),
+            (":", token::Colon),
+            ("[", token::LeftBracket),
+            ("]", token::RightBracket),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            assert_eq!(lex.next(), Some(expected.clone()), "{}", input);
+            assert_eq!(lex.next(), None, "{}", input);
+            assert_eq!(expected.to_string(), *input);
+        }
+
+        // The longest operator wins, then lexing goes on after it.
+        let tests = [
+            ("***", vec![token::Power, token::Asterisk]),
+            ("**=", vec![token::Power, token::Assign]),
+            ("<<=", vec![token::ShiftLeft, token::Assign]),
+            (">>=", vec![token::ShiftRight, token::Assign]),
+            ("<<<", vec![token::ShiftLeft, token::LowerThan]),
+            ("&&&", vec![token::And, token::Ampersand]),
+            ("|||", vec![token::Or, token::Pipe]),
+            ("...", vec![token::DotDot, token::Dot]),
+            ("===", vec![token::Eq, token::Assign]),
+            ("==>", vec![token::Eq, token::GreaterThan]),
+            ("->>", vec![token::Arrow, token::GreaterThan]),
+            ("--=", vec![token::Minus, token::MinusAssign]),
+            ("!==", vec![token::NotEq, token::Assign]),
+            ("& &", vec![token::Ampersand, token::Ampersand]),
+            ("a>b", vec![token::Ident(String::from("a")), token::GreaterThan, token::Ident(String::from("b"))]),
+            ("1..10", vec![token::Int(1), token::DotDot, token::Int(10)]),
+            ("0.5..x.y", vec![
+                token::Float(0.5),
+                token::DotDot,
+                token::Ident(String::from("x")),
+                token::Dot,
+                token::Ident(String::from("y")),
+            ]),
+            ("x /= 2 // halved", vec![token::Ident(String::from("x")), token::SlashAssign, token::Int(2)]),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let lex = crate::lexer::Lexer::new(String::from(*input));
+            assert_eq!(&lex.collect::<Vec<token::Token>>(), expected, "{}", input);
+        }
+
+        let mut lex = crate::lexer::Lexer::new(String::from("x **= 2"));
+        assert_eq!(spanned_tokens(&mut lex), vec![
+            (token::Ident(String::from("x")), Span::new(0, 1, 1, 1)),
+            (token::Power, Span::new(2, 2, 1, 3)),
+            (token::Assign, Span::new(4, 1, 1, 5)),
+            (token::Int(2), Span::new(6, 1, 1, 7)),
+        ]);
+        assert!(lex.diagnostics().is_empty());
+    }
+
+    #[test]
+    pub fn test_identifiers() {
+        let tests = [
+            ("snake_case", "snake_case"),
+            ("_", "_"),
+            ("_private", "_private"),
+            ("__dunder__", "__dunder__"),
+            ("x_1", "x_1"),
+            ("été", "été"),
+            ("caf\u{65}\u{301}", "caf\u{e9}"),
+            ("данные", "данные"),
+            ("名前の", "名前の"),
+            ("x\u{663}", "x\u{663}"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let mut lex = crate::lexer::Lexer::new(String::from(*input));
+            assert_eq!(lex.next(), Some(token::Ident(String::from(*expected))), "{}", input);
+            assert_eq!(lex.next(), None, "{}", input);
+            assert!(lex.diagnostics().is_empty(), "{}", input);
+        }
+
+        let lex = crate::lexer::Lexer::new(String::from("1_x x-y \u{301}a _let let_ €"));
+        assert_eq!(lex.collect::<Vec<token::Token>>(), vec![
+            token::Int(1),
+            token::Ident(String::from("x")),
+            token::Ident(String::from("x")),
+            token::Minus,
+            token::Ident(String::from("y")),
+            token::Illegal('\u{301}'),
+            token::Ident(String::from("a")),
+            token::Ident(String::from("_let")),
+            token::Ident(String::from("let_")),
+            token::Illegal('€'),
+        ]);
+
+        // The span covers the identifier as written, before normalization.
+        let mut lex = crate::lexer::Lexer::new(String::from("let e\u{301} = 1;"));
+        let tokens = spanned_tokens(&mut lex);
+        assert_eq!(tokens[1], (token::Ident(String::from("\u{e9}")), Span::new(4, 3, 1, 5)));
+        assert_eq!(tokens[2].1, Span::new(8, 1, 1, 8));
+    }
+
+    #[test]
+    pub fn test_mixed_script_identifiers() {
+        let mut lex = crate::lexer::Lexer::new(String::from("let p\u{430}ypal = \u{3bb}x;"));
+        assert_eq!(spanned_tokens(&mut lex)[1], (token::Ident(String::from("p\u{430}ypal")), Span::new(4, 7, 1, 5)));
+        let diagnostics = lex.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].message, "the identifier `p\u{430}ypal` mixes the Latin and Cyrillic scripts");
+        assert_eq!(diagnostics[0].span, Span::new(4, 7, 1, 5));
+    }
+
+    fn spanned_tokens<Src: std::io::BufRead>(lex: &mut crate::lexer::Lexer<Src>) -> Vec<(token::Token, Span)> {
+        std::iter::from_fn(|| lex.next_spanned()).collect()
+    }
+
+    #[test]
+    pub fn test_reader() {
+        let input = "let s = \"multi\nline \\u{e9}\";\r\n\tlet é = [0x1f, 2.5e3] # \"\\q\"\n\n\"open";
+        let mut from_string = crate::lexer::Lexer::new(String::from(input));
+        let expected = spanned_tokens(&mut from_string);
+
+        for capacity in [1, 2, 4096].iter() {
+            let mut lex = crate::lexer::Lexer::from_reader(std::io::BufReader::with_capacity(*capacity, input.as_bytes()));
+            assert_eq!(spanned_tokens(&mut lex), expected);
+            assert_eq!(lex.diagnostics(), from_string.diagnostics());
+            assert_eq!(lex.eof_span(), from_string.eof_span());
+        }
+
+        let line = "let value = fn(x) { x + 1 };\n";
+        let input = line.repeat(1000);
+        let mut lex = crate::lexer::Lexer::from_reader(input.as_bytes());
+        let mut count = 0;
+        while lex.next().is_some() {
+            assert!(lex.buffer.len() <= 2 * line.len(), "{}", lex.buffer.len());
+            count += 1;
+        }
+        assert_eq!(count, 13 * 1000);
+    }
+
+    #[test]
+    pub fn test_reader_errors() {
+        let mut lex = crate::lexer::Lexer::from_reader(&b"let x = 1;\n\xff 2\n"[..]);
+        let tokens: Vec<token::Token> = lex.by_ref().collect();
+        assert_eq!(tokens, vec![token::Let, token::Ident(String::from("x")), token::Assign, token::Int(1), token::Semicolon]);
+        assert_eq!(
+            lex.diagnostics(),
+            &[Diagnostic::error("cannot read the input: stream did not contain valid UTF-8", Span::new(11, 0, 2, 1))]
+        );
+    }
+
+    #[test]
+    pub fn test_comments() {
+        let input = "let a = 1; // comment\n/* block /* nested */\n */ a /**/ / 2\n/// Doc  text\r\n//// not doc\nlet b = a;\n///";
+        let expected = vec![
+            (token::Let, Span::new(0, 3, 1, 1)),
+            (token::Ident(String::from("a")), Span::new(4, 1, 1, 5)),
+            (token::Assign, Span::new(6, 1, 1, 7)),
+            (token::Int(1), Span::new(8, 1, 1, 9)),
+            (token::Semicolon, Span::new(9, 1, 1, 10)),
+            (token::Ident(String::from("a")), Span::new(48, 1, 3, 5)),
+            (token::Slash, Span::new(55, 1, 3, 12)),
+            (token::Int(2), Span::new(57, 1, 3, 14)),
+            (token::DocComment(String::from(" Doc  text")), Span::new(59, 13, 4, 1)),
+            (token::Let, Span::new(87, 3, 6, 1)),
+            (token::Ident(String::from("b")), Span::new(91, 1, 6, 5)),
+            (token::Assign, Span::new(93, 1, 6, 7)),
+            (token::Ident(String::from("a")), Span::new(95, 1, 6, 9)),
+            (token::Semicolon, Span::new(96, 1, 6, 10)),
+            (token::DocComment(String::new()), Span::new(98, 3, 7, 1)),
+        ];
+
+        let mut lex = crate::lexer::Lexer::new(String::from(input));
+        assert_eq!(spanned_tokens(&mut lex), expected);
+        assert!(lex.diagnostics().is_empty());
+        let mut lex = crate::lexer::Lexer::from_reader(std::io::BufReader::with_capacity(1, input.as_bytes()));
+        assert_eq!(spanned_tokens(&mut lex), expected);
+
+        let mut lex = crate::lexer::Lexer::new(String::from("1 /* a /* b */\n2"));
+        assert_eq!(lex.by_ref().collect::<Vec<token::Token>>(), vec![token::Int(1)]);
+        assert_eq!(
+            lex.diagnostics(),
+            &[Diagnostic::error("unterminated block comment", Span::new(2, 2, 1, 3))
+                .with_label("the comment starts here")
+                .with_help("add a `*/` at the end of the comment")]
+        );
+    }
+}
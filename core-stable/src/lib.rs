@@ -1,2 +1,8 @@
+pub mod span;
+pub mod diagnostics;
 pub mod token;
+pub mod ident;
 pub mod lexer;
+pub mod tokens;
+#[cfg(feature = "logos")]
+pub mod logos_lexer;
@@ -0,0 +1,435 @@
+use std::ops::Range;
+
+use logos::{ Filter, Logos };
+
+use super::diagnostics::Diagnostic;
+use super::ident;
+use super::lexer::IntPrefix;
+use super::span::Span;
+use super::token;
+
+/// A problem found by the callbacks, turned into a `Diagnostic` once its
+/// line and column are known.
+struct Problem {
+    range: Range<usize>,
+    kind: ProblemKind,
+}
+
+enum ProblemKind {
+    InvalidEscape,
+    UnterminatedString,
+    UnterminatedComment,
+    Number(String),
+    /// Found by a function of another module, which already gives the
+    /// diagnostic but for its span.
+    Diagnostic(Diagnostic),
+}
+
+/// The tokens matched by the generated automaton. The literals, strings and
+/// comments are read by hand from their first characters, the same way as
+/// `Lexer` does.
+#[derive(Logos)]
+#[logos(skip r"[ \t\n\r]+")]
+#[logos(extras = Vec<Problem>)]
+enum Raw {
+    #[token("=", |_| token::Assign)]
+    #[token("==", |_| token::Eq)]
+    #[token("+", |_| token::Plus)]
+    #[token("-", |_| token::Minus)]
+    #[token("!", |_| token::Bang)]
+    #[token("!=", |_| token::NotEq)]
+    #[token("*", |_| token::Asterisk)]
+    #[token("/", |_| token::Slash)]
+    #[token("<", |_| token::LowerThan)]
+    #[token("<=", |_| token::LowerThanOrEqualTo)]
+    #[token(">", |_| token::GreaterThan)]
+    #[token(">=", |_| token::GreaterThanOrEqualTo)]
+    #[token("=>", |_| token::FatArrow)]
+    #[token("+=", |_| token::PlusAssign)]
+    #[token("-=", |_| token::MinusAssign)]
+    #[token("->", |_| token::Arrow)]
+    #[token("/=", |_| token::SlashAssign)]
+    #[token("**", |_| token::Power)]
+    #[token("*=", |_| token::AsteriskAssign)]
+    #[token("%", |_| token::Percent)]
+    #[token("&", |_| token::Ampersand)]
+    #[token("&&", |_| token::And)]
+    #[token("|", |_| token::Pipe)]
+    #[token("||", |_| token::Or)]
+    #[token("^", |_| token::Caret)]
+    #[token("~", |_| token::Tilde)]
+    #[token("<<", |_| token::ShiftLeft)]
+    #[token(">>", |_| token::ShiftRight)]
+    #[token(".", |_| token::Dot)]
+    #[token("..", |_| token::DotDot)]
+    #[token(";", |_| token::Semicolon)]
+    #[token(",", |_| token::Comma)]
+    #[token(":", |_| token::Colon)]
+    #[token("{", |_| token::LeftBrace)]
+    #[token("}", |_| token::RightBrace)]
+    #[token("(", |_| token::LeftParen)]
+    #[token(")", |_| token::RightParen)]
+    #[token("[", |_| token::LeftBracket)]
+    #[token("]", |_| token::RightBracket)]
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*", identifier)]
+    #[regex("[0-9]", number)]
+    #[token("\"", string)]
+    #[token("//", line_comment)]
+    #[token("///", line_comment)]
+    #[token("/*", block_comment)]
+    Token(token::Token),
+}
+
+/// The character at byte offset `offset` of `source`.
+fn char_at(source: &str, offset: usize) -> Option<char> {
+    source[offset..].chars().next()
+}
+
+/// Reads the digits of `prefix` from byte offset `offset`, skipping the `_`
+/// separators, and returns the offset after them.
+fn read_digits(source: &str, mut offset: usize, prefix: &IntPrefix, buf: &mut String) -> usize {
+    while let Some(&byte) = source.as_bytes().get(offset) {
+        if prefix.is_digit(byte as char) {
+            buf.push(byte as char);
+        } else if byte != b'_' {
+            break;
+        }
+        offset += 1;
+    }
+    offset
+}
+
+fn number(lex: &mut logos::Lexer<Raw>) -> token::Token {
+    let source = lex.source();
+    let bytes = source.as_bytes();
+    let start = lex.span().start;
+    let prefix = IntPrefix::from(source.get(start..start + 2).unwrap_or(""));
+    let mut end = match prefix {
+        IntPrefix::None => start,
+        _ => start + 2,
+    };
+    let mut buf = String::new();
+    end = read_digits(source, end, &prefix, &mut buf);
+    let mut float = false;
+    if let IntPrefix::None = prefix {
+        let is_digit = |offset: usize| bytes.get(offset).is_some_and(u8::is_ascii_digit);
+        if bytes.get(end) == Some(&b'.') && is_digit(end + 1) {
+            float = true;
+            buf.push('.');
+            end = read_digits(source, end + 1, &prefix, &mut buf);
+        }
+        if let Some(&exponent) = bytes.get(end).filter(|byte| matches!(byte, b'e' | b'E')) {
+            let signed = matches!(bytes.get(end + 1), Some(b'+') | Some(b'-'));
+            let digit = if signed { 2 } else { 1 };
+            if is_digit(end + digit) {
+                float = true;
+                buf.push(exponent as char);
+                if signed {
+                    buf.push(bytes[end + 1] as char);
+                }
+                end = read_digits(source, end + digit, &prefix, &mut buf);
+            }
+        }
+    }
+    lex.bump(end - lex.span().end);
+
+    prefix.parse_literal(&buf, float).unwrap_or_else(|message| {
+        lex.extras.push(Problem { range: start..end, kind: ProblemKind::Number(message) });
+        token::Illegal(bytes[start] as char)
+    })
+}
+
+fn identifier(lex: &mut logos::Lexer<Raw>) -> token::Token {
+    let name = ident::normalize(lex.slice());
+    if let Some(warning) = ident::mixed_script_warning(&name, Span::default()) {
+        lex.extras.push(Problem { range: lex.span(), kind: ProblemKind::Diagnostic(warning) });
+    }
+    token::lookup_indent(&name)
+}
+
+fn string(lex: &mut logos::Lexer<Raw>) -> token::Token {
+    let source = lex.source();
+    let start = lex.span().start;
+    let mut offset = start + 1;
+    let mut buf = String::new();
+    let mut valid = true;
+    loop {
+        match char_at(source, offset) {
+            Some('"') => break,
+            Some('\\') => {
+                let (escaped, end) = escape(source, offset + 1);
+                match escaped {
+                    Some(ch) => buf.push(ch),
+                    None => {
+                        valid = false;
+                        lex.extras.push(Problem { range: offset..end, kind: ProblemKind::InvalidEscape });
+                    }
+                }
+                offset = end;
+            },
+            Some(ch) => {
+                buf.push(ch);
+                offset += ch.len_utf8();
+            },
+            None => {
+                lex.extras.push(Problem { range: start..start + 1, kind: ProblemKind::UnterminatedString });
+                lex.bump(source.len() - lex.span().end);
+                return token::Illegal('"');
+            }
+        }
+    }
+    lex.bump(offset + 1 - lex.span().end);
+    if valid { token::String(buf) } else { token::Illegal('"') }
+}
+
+/// Reads the escape sequence following a `\` at byte offset `offset`,
+/// returning the escaped character and the offset after the sequence.
+fn escape(source: &str, offset: usize) -> (Option<char>, usize) {
+    let ch = match char_at(source, offset) {
+        Some(ch) => ch,
+        None => return (None, offset),
+    };
+    let offset = offset + ch.len_utf8();
+    match ch {
+        'n' => (Some('\n'), offset),
+        'r' => (Some('\r'), offset),
+        't' => (Some('\t'), offset),
+        '0' => (Some('\0'), offset),
+        '"' => (Some('"'), offset),
+        '\\' => (Some('\\'), offset),
+        'u' if char_at(source, offset) == Some('{') => {
+            let start = offset + 1;
+            let end = start + source[start..].bytes().take_while(u8::is_ascii_hexdigit).count();
+            if char_at(source, end) != Some('}') {
+                return (None, end);
+            }
+            let digits = &source[start..end];
+            if digits.is_empty() || digits.len() > 6 {
+                return (None, end + 1);
+            }
+            let escaped = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+            (escaped, end + 1)
+        },
+        _ => (None, offset),
+    }
+}
+
+/// Skips a `//` comment, or reads a `///` doc comment. Four slashes or more
+/// make a regular comment.
+fn line_comment(lex: &mut logos::Lexer<Raw>) -> Filter<token::Token> {
+    let source = lex.source();
+    let start = lex.span().start;
+    let end = source[start..].find('\n').map_or(source.len(), |len| start + len);
+    if lex.slice() == "///" && !source[start..].starts_with("////") {
+        let text_end = if end < source.len() && source[..end].ends_with('\r') { end - 1 } else { end };
+        lex.bump(text_end - lex.span().end);
+        Filter::Emit(token::DocComment(String::from(&source[start + 3..text_end])))
+    } else {
+        lex.bump(end - lex.span().end);
+        Filter::Skip
+    }
+}
+
+/// Skips a block comment, along with the block comments nested in it.
+fn block_comment(lex: &mut logos::Lexer<Raw>) -> Filter<token::Token> {
+    let source = lex.source();
+    let start = lex.span().start;
+    let mut offset = lex.span().end;
+    let mut depth = 1;
+    while depth > 0 {
+        let rest = &source[offset..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            offset += 2;
+        } else if rest.starts_with("*/") {
+            depth -= 1;
+            offset += 2;
+        } else if let Some(ch) = rest.chars().next() {
+            offset += ch.len_utf8();
+        } else {
+            lex.extras.push(Problem { range: start..start + 2, kind: ProblemKind::UnterminatedComment });
+            break;
+        }
+    }
+    lex.bump(offset - lex.span().end);
+    Filter::Skip
+}
+
+/// A lexer generated by logos, giving the same tokens, spans and diagnostics
+/// as `Lexer` but working on a borrowed `&str` only.
+pub struct LogosLexer<'a> {
+    inner: logos::Lexer<'a, Raw>,
+    /// Byte offset, line and column of the last located character.
+    located: (usize, usize, usize),
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> LogosLexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        LogosLexer {
+            inner: Raw::lexer(source),
+            located: (0, 1, 1),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Span of `range`. The characters are walked from the last located
+    /// one, or from the start when `range` is before it, which only happens
+    /// for an unterminated string with invalid escapes.
+    fn locate(&mut self, range: Range<usize>) -> Span {
+        if range.start < self.located.0 {
+            self.located = (0, 1, 1);
+        }
+        let (mut offset, mut line, mut column) = self.located;
+        for ch in self.inner.source()[offset..range.start].chars() {
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+            offset += ch.len_utf8();
+        }
+        self.located = (offset, line, column);
+        Span::new(range.start, range.len(), line, column)
+    }
+
+    /// Empty span at the end of the input.
+    pub fn eof_span(&mut self) -> Span {
+        let len = self.inner.source().len();
+        self.locate(len..len)
+    }
+
+    fn report(&mut self, problem: Problem) {
+        let span = self.locate(problem.range.clone());
+        let diagnostic = match problem.kind {
+            ProblemKind::InvalidEscape => {
+                let text = &self.inner.source()[problem.range];
+                Diagnostic::error(format!("invalid escape sequence `{}`", text), span)
+                    .with_help("the escape sequences are `\\n`, `\\r`, `\\t`, `\\0`, `\\\"`, `\\\\` and `\\u{...}`")
+            },
+            ProblemKind::UnterminatedString => Diagnostic::error("unterminated string", span)
+                .with_label("the string starts here")
+                .with_help("add a `\"` at the end of the string"),
+            ProblemKind::UnterminatedComment => Diagnostic::error("unterminated block comment", span)
+                .with_label("the comment starts here")
+                .with_help("add a `*/` at the end of the comment"),
+            ProblemKind::Number(message) => Diagnostic::error(message, span),
+            ProblemKind::Diagnostic(diagnostic) => Diagnostic { span, ..diagnostic },
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Next token, with the span of the source it comes from.
+    pub fn next_spanned(&mut self) -> Option<(token::Token, Span)> {
+        let raw = self.inner.next();
+        let mut range = self.inner.span();
+        let problems = std::mem::take(&mut self.inner.extras);
+        // The problems of the comments skipped before the token come first.
+        let (before, within): (Vec<Problem>, Vec<Problem>) =
+            problems.into_iter().partition(|problem| raw.is_none() || problem.range.start < range.start);
+        for problem in before {
+            self.report(problem);
+        }
+        let illegal = matches!(raw, Some(Err(())));
+        let tok = match raw? {
+            Ok(Raw::Token(tok)) => tok,
+            Err(()) => {
+                let ch = self.inner.source()[range.start..].chars().next()?;
+                range.end = range.start + ch.len_utf8();
+                if self.inner.span().end < range.end {
+                    self.inner.bump(range.end - self.inner.span().end);
+                }
+                token::Illegal(ch)
+            },
+        };
+        let span = self.locate(range);
+        for problem in within {
+            self.report(problem);
+        }
+        if let (token::Illegal(ch), true) = (&tok, illegal) {
+            self.diagnostics.push(
+                Diagnostic::error(format!("illegal character `{}`", ch), span)
+                    .with_label("not part of the language")
+            );
+        }
+        Some((tok, span))
+    }
+
+    /// Problems found in the tokens read so far.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl<'a> Iterator for LogosLexer<'a> {
+    type Item = token::Token;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|(tok, _)| tok)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::lexer::test::NEXT_TOKEN_INPUT;
+
+    const CORPUS: &[&str] = &[
+        NEXT_TOKEN_INPUT,
+        r#"
+/// The `n`th Fibonacci number.
+let fib = fn(n) {
+    if (n <= 1) { return n; }
+    fib(n - 1) + fib(n - 2) // exponential /* not a block */
+};
+/* A block comment /* with a nested one */
+   on several lines. */
+let map = fn(array, f) {
+    let iter = fn(rest, acc) {
+        if (len(rest) == 0) { acc } else { iter(rest(rest), push(acc, f(first(rest)))) }
+    };
+    iter(array, [])
+};
+puts(map([1, 2, 3], fn(x) { x * 2.5e-1 }), fib(0x1F), {"key": [0b1010, 0o17, 1_000.000_1]});
+let unless = macro(condition, consequence) { quote(if (!(unquote(condition))) { unquote(consequence) }) };
+unless(10 >= 5, puts("not greater"));
+////  Not a doc comment.
+///
+"#,
+        "let s = \"été \\u{1F600} \\\"quoted\\\" \\\\ \\n\";\r\n/// Doc\r\nlet été = 3.14;\n",
+        "let x = 1 # 2 @ 3 ٣ 4;\n let big = 99999999999999999999 + 1e999 + 0x;",
+        r#"let s = "a\qb" + "\u{110000}" + "\u{}" + "\u{12" + "\u{1234567}"; "end"#,
+        "1.foo 2e 3.5.6 1e+ 7e-2 0b12 0o78 0xfg 1__2_ 1_.5",
+        "x /* unterminated /* nested */",
+        "a // trailing comment without newline",
+        "a /// doc at the end\r",
+        "a % b ** 2 *** c **= d; e && f || g & h | i ^ ~j << 1 >> 2 <<= 3 >>= 4 >>> 5;",
+        "x += 1; y -= 2; z *= 3; w /= 4 //= comment\nf = fn(x) -> x => x; a.b.c; 1..10 ... 0.5..2.5e1 1.x",
+        "===!==>=<=->-->..=.",
+        "let snake_case = _private + __dunder__ + _1 + x_ + _;\nlet caf\u{65}\u{301} = caf\u{e9};\nlet p\u{430}ypal = \u{3bb}x + \u{441}\u{43e}\u{440}t + \u{301}a + 名前の;",
+       "\"\\",
+        "",
+    ];
+
+    fn spanned(next: impl FnMut() -> Option<(token::Token, Span)>) -> Vec<(token::Token, Span)> {
+        std::iter::from_fn(next).collect()
+    }
+
+    fn assert_same_as_lexer(input: &str) {
+        let mut lexer = Lexer::new(String::from(input));
+        let mut logos_lexer = LogosLexer::new(input);
+        assert_eq!(spanned(|| logos_lexer.next_spanned()), spanned(|| lexer.next_spanned()), "{:?}", input);
+        assert_eq!(logos_lexer.diagnostics(), lexer.diagnostics(), "{:?}", input);
+        assert_eq!(logos_lexer.eof_span(), lexer.eof_span(), "{:?}", input);
+    }
+
+    #[test]
+    pub fn test_same_tokens_as_lexer() {
+        for input in CORPUS {
+            assert_same_as_lexer(input);
+        }
+        assert_same_as_lexer(&CORPUS[1].repeat(200));
+        assert_same_as_lexer(&CORPUS.concat());
+    }
+}
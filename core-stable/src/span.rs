@@ -0,0 +1,51 @@
+/// A range of the source code.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Hash)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub offset: usize,
+    /// Length in bytes.
+    pub len: usize,
+    /// Line of the first character, starting at 1.
+    pub line: usize,
+    /// Column of the first character, in characters, starting at 1.
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(offset: usize, len: usize, line: usize, column: usize) -> Self {
+        Self {
+            offset,
+            len,
+            line,
+            column,
+        }
+    }
+
+    /// Byte offset just after the last character.
+    pub fn end(&self) -> usize {
+        self.offset + self.len
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.offset <= other.offset { (self, other) } else { (other, self) };
+        Span {
+            len: std::cmp::max(first.end(), last.end()) - first.offset,
+            ..first
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_to() {
+        let a = Span::new(4, 3, 1, 5);
+        let b = Span::new(10, 2, 2, 1);
+        assert_eq!(a.to(b), Span::new(4, 8, 1, 5));
+        assert_eq!(b.to(a), Span::new(4, 8, 1, 5));
+        assert_eq!(a.to(Span::new(5, 1, 1, 6)), a);
+    }
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
 use std::cmp::PartialEq;
+use std::fmt;
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
@@ -14,6 +14,9 @@ pub enum Token {
     String(std::string::String),
     Boolean(bool),
 
+    /// A `///` comment, holding the text after the slashes
+    DocComment(std::string::String),
+
     /// Operators
     Assign,
     Plus,
@@ -21,6 +24,23 @@ pub enum Token {
     Bang,
     Asterisk,
     Slash,
+    Percent,
+    Power,
+
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+
+    And,
+    Or,
+
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
 
     LowerThan,
     GreaterThan,
@@ -33,14 +53,22 @@ pub enum Token {
     /// Delimiters
     Comma,
     Semicolon,
+    Colon,
+    Dot,
+    DotDot,
+    Arrow,
+    FatArrow,
 
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
 
     /// Keywords
     Function,
+    Macro,
     Let,
     If,
     Else,
@@ -50,34 +78,84 @@ pub enum Token {
 
 pub use Token::*;
 
-macro_rules! map {
-    ( $( $x:expr => $y:expr),* ) => {
-        {
-            let mut temp_map: HashMap<&'static str, Token> = HashMap::new();
-            $(
-                temp_map.insert($x, $y);
-            )*
-            temp_map
+/// Writes the token as it appears in the source.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Illegal(ch) => write!(f, "{}", ch),
+            EndOfFile => write!(f, "end of file"),
+            Ident(ident) => write!(f, "{}", ident),
+            Int(value) => write!(f, "{}", value),
+            Float(value) => write!(f, "{:?}", value),
+            String(value) => write!(f, "{:?}", value),
+            Boolean(value) => write!(f, "{}", value),
+            DocComment(text) => write!(f, "///{}", text),
+            Assign => write!(f, "="),
+            Plus => write!(f, "+"),
+            Minus => write!(f, "-"),
+            Bang => write!(f, "!"),
+            Asterisk => write!(f, "*"),
+            Slash => write!(f, "/"),
+            Percent => write!(f, "%"),
+            Power => write!(f, "**"),
+            PlusAssign => write!(f, "+="),
+            MinusAssign => write!(f, "-="),
+            AsteriskAssign => write!(f, "*="),
+            SlashAssign => write!(f, "/="),
+            And => write!(f, "&&"),
+            Or => write!(f, "||"),
+            Ampersand => write!(f, "&"),
+            Pipe => write!(f, "|"),
+            Caret => write!(f, "^"),
+            Tilde => write!(f, "~"),
+            ShiftLeft => write!(f, "<<"),
+            ShiftRight => write!(f, ">>"),
+            LowerThan => write!(f, "<"),
+            GreaterThan => write!(f, ">"),
+            LowerThanOrEqualTo => write!(f, "<="),
+            GreaterThanOrEqualTo => write!(f, ">="),
+            Eq => write!(f, "=="),
+            NotEq => write!(f, "!="),
+            Comma => write!(f, ","),
+            Semicolon => write!(f, ";"),
+            Colon => write!(f, ":"),
+            Dot => write!(f, "."),
+            DotDot => write!(f, ".."),
+            Arrow => write!(f, "->"),
+            FatArrow => write!(f, "=>"),
+            LeftParen => write!(f, "("),
+            RightParen => write!(f, ")"),
+            LeftBrace => write!(f, "{{"),
+            RightBrace => write!(f, "}}"),
+            LeftBracket => write!(f, "["),
+            RightBracket => write!(f, "]"),
+            Function => write!(f, "fn"),
+            Macro => write!(f, "macro"),
+            Let => write!(f, "let"),
+            If => write!(f, "if"),
+            Else => write!(f, "else"),
+            Return => write!(f, "return"),
+            Unit => write!(f, "()"),
         }
-    };
+    }
 }
 
 #[allow(dead_code)]
 pub fn lookup_indent(ident: &str) -> Token {
-    let keywords: HashMap<&str, Token> = map!{
-        "fn" => Function,
-        "let" => Let,
-        "true" =>  Boolean(true),
-        "false" => Boolean(false),
-        "if" => If,
-        "else" => Else,
-        "return" => Return
-    };
-    match keywords.get(ident) {
-        Some(value) => {
-            value.clone()
-        } None => {
-            Ident(std::string::String::from(ident))
-        }
+    lookup_keyword(ident).unwrap_or_else(|| Ident(std::string::String::from(ident)))
+}
+
+/// The keyword `ident` is, if any.
+pub fn lookup_keyword(ident: &str) -> Option<Token> {
+    match ident {
+        "fn" => Some(Function),
+        "macro" => Some(Macro),
+        "let" => Some(Let),
+        "true" => Some(Boolean(true)),
+        "false" => Some(Boolean(false)),
+        "if" => Some(If),
+        "else" => Some(Else),
+        "return" => Some(Return),
+        _ => None
     }
 }
\ No newline at end of file
@@ -0,0 +1,352 @@
+use super::diagnostics::Diagnostic;
+use super::lexer::Lexer;
+use super::span::Span;
+use super::token;
+
+/// A token borrowing its text from the source, unlike `token::Token`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Token<'a> {
+    Illegal(char),
+    EndOfFile,
+
+    /// The identifier as written, while `token::Ident` holds its NFC form.
+    Ident(&'a str),
+    Int(isize),
+    Float(f64),
+    /// The source between the quotes, escape sequences included.
+    String(&'a str),
+    Boolean(bool),
+
+    /// The text after the slashes.
+    DocComment(&'a str),
+
+    Assign,
+    Plus,
+    Minus,
+    Bang,
+    Asterisk,
+    Slash,
+    Percent,
+    Power,
+
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+
+    And,
+    Or,
+
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+
+    LowerThan,
+    GreaterThan,
+    LowerThanOrEqualTo,
+    GreaterThanOrEqualTo,
+
+    Eq,
+    NotEq,
+
+    Comma,
+    Semicolon,
+    Colon,
+    Dot,
+    DotDot,
+    Arrow,
+    FatArrow,
+
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+
+    Function,
+    Macro,
+    Let,
+    If,
+    Else,
+    Return,
+    Unit,
+}
+
+impl<'a> Token<'a> {
+    /// The borrowed version of `tok`, read from `source` at `span`.
+    pub fn borrow(tok: &token::Token, source: &'a str, span: Span) -> Token<'a> {
+        let text = &source[span.offset..span.end()];
+        match tok {
+            token::Illegal(ch) => Token::Illegal(*ch),
+            token::EndOfFile => Token::EndOfFile,
+            token::Ident(_) => Token::Ident(text),
+            token::Int(value) => Token::Int(*value),
+            token::Float(value) => Token::Float(*value),
+            token::String(_) => Token::String(&text[1..text.len() - 1]),
+            token::Boolean(value) => Token::Boolean(*value),
+            token::DocComment(_) => Token::DocComment(&text[3..]),
+            token::Assign => Token::Assign,
+            token::Plus => Token::Plus,
+            token::Minus => Token::Minus,
+            token::Bang => Token::Bang,
+            token::Asterisk => Token::Asterisk,
+            token::Slash => Token::Slash,
+            token::Percent => Token::Percent,
+            token::Power => Token::Power,
+            token::PlusAssign => Token::PlusAssign,
+            token::MinusAssign => Token::MinusAssign,
+            token::AsteriskAssign => Token::AsteriskAssign,
+            token::SlashAssign => Token::SlashAssign,
+            token::And => Token::And,
+            token::Or => Token::Or,
+            token::Ampersand => Token::Ampersand,
+            token::Pipe => Token::Pipe,
+            token::Caret => Token::Caret,
+            token::Tilde => Token::Tilde,
+            token::ShiftLeft => Token::ShiftLeft,
+            token::ShiftRight => Token::ShiftRight,
+            token::LowerThan => Token::LowerThan,
+            token::GreaterThan => Token::GreaterThan,
+            token::LowerThanOrEqualTo => Token::LowerThanOrEqualTo,
+            token::GreaterThanOrEqualTo => Token::GreaterThanOrEqualTo,
+            token::Eq => Token::Eq,
+            token::NotEq => Token::NotEq,
+            token::Comma => Token::Comma,
+            token::Semicolon => Token::Semicolon,
+            token::Colon => Token::Colon,
+            token::Dot => Token::Dot,
+            token::DotDot => Token::DotDot,
+            token::Arrow => Token::Arrow,
+            token::FatArrow => Token::FatArrow,
+            token::LeftParen => Token::LeftParen,
+            token::RightParen => Token::RightParen,
+            token::LeftBrace => Token::LeftBrace,
+            token::RightBrace => Token::RightBrace,
+            token::LeftBracket => Token::LeftBracket,
+            token::RightBracket => Token::RightBracket,
+            token::Function => Token::Function,
+            token::Macro => Token::Macro,
+            token::Let => Token::Let,
+            token::If => Token::If,
+            token::Else => Token::Else,
+            token::Return => Token::Return,
+            token::Unit => Token::Unit,
+        }
+    }
+
+    /// Whether both tokens are of the same kind, whatever their values.
+    pub fn same_kind(&self, other: &Token) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// The tokens of a source, with their spans and the problems found by the lexer.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Tokenized<'a> {
+    pub tokens: Vec<Token<'a>>,
+    /// The span of each token.
+    pub spans: Vec<Span>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Tokenized<'a> {
+    pub fn tokens(&self) -> Tokens<'_> {
+        Tokens(&self.tokens)
+    }
+}
+
+/// Lexes `source` into tokens borrowing their text from it. Neither the
+/// source nor the text of the tokens is copied.
+pub fn tokenize(source: &str) -> Tokenized<'_> {
+    let mut lex = Lexer::borrowed(source);
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    while let Some((tok, span)) = lex.next_kind_spanned() {
+        tokens.push(Token::borrow(&tok, source, span));
+        spans.push(span);
+    }
+    Tokenized {
+        tokens,
+        spans,
+        diagnostics: lex.diagnostics().to_vec(),
+    }
+}
+
+/// A slice of tokens, the input of the combinator parsers. With the `nom`
+/// feature, it implements the input traits of nom, and `tag` compares the
+/// kinds of the tokens only, so that `tag(Tokens(&[Token::Ident("")]))`
+/// matches any identifier.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Tokens<'a>(pub &'a [Token<'a>]);
+
+#[cfg(feature = "nom")]
+mod nom_input {
+    use std::iter::Enumerate;
+    use std::ops::{ Range, RangeFrom, RangeFull, RangeTo };
+    use std::slice::Iter;
+
+    use nom::{ Compare, CompareResult, InputIter, InputLength, InputTake, Needed, Slice };
+
+    use super::{ Token, Tokens };
+
+    impl<'a> InputLength for Tokens<'a> {
+        fn input_len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl<'a> InputLength for Token<'a> {
+        fn input_len(&self) -> usize {
+            1
+        }
+    }
+
+    impl<'a> InputTake for Tokens<'a> {
+        fn take(&self, count: usize) -> Self {
+            Tokens(&self.0[..count])
+        }
+
+        fn take_split(&self, count: usize) -> (Self, Self) {
+            let (prefix, suffix) = self.0.split_at(count);
+            (Tokens(suffix), Tokens(prefix))
+        }
+    }
+
+    impl<'a, 'b> Compare<Tokens<'b>> for Tokens<'a> {
+        fn compare(&self, expected: Tokens<'b>) -> CompareResult {
+            let matching = self.0.iter().zip(expected.0).all(|(tok, expected)| tok.same_kind(expected));
+            if !matching {
+                CompareResult::Error
+            } else if self.0.len() < expected.0.len() {
+                CompareResult::Incomplete
+            } else {
+                CompareResult::Ok
+            }
+        }
+
+        fn compare_no_case(&self, expected: Tokens<'b>) -> CompareResult {
+            self.compare(expected)
+        }
+    }
+
+    macro_rules! impl_slice {
+        ( $( $range:ty ),* ) => {
+            $(
+                impl<'a> Slice<$range> for Tokens<'a> {
+                    fn slice(&self, range: $range) -> Self {
+                        Tokens(&self.0[range])
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_slice!(Range<usize>, RangeTo<usize>, RangeFrom<usize>, RangeFull);
+
+    impl<'a> InputIter for Tokens<'a> {
+        type Item = &'a Token<'a>;
+        type Iter = Enumerate<Iter<'a, Token<'a>>>;
+        type IterElem = Iter<'a, Token<'a>>;
+
+        fn iter_indices(&self) -> Self::Iter {
+            self.0.iter().enumerate()
+        }
+
+        fn iter_elements(&self) -> Self::IterElem {
+            self.0.iter()
+        }
+
+        fn position<P: Fn(Self::Item) -> bool>(&self, predicate: P) -> Option<usize> {
+            self.0.iter().position(predicate)
+        }
+
+        fn slice_index(&self, count: usize) -> Result<usize, Needed> {
+            if self.0.len() >= count {
+                Ok(count)
+            } else {
+                Err(Needed::new(count - self.0.len()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_tokenize() {
+        let source = "let é = \"a\\nb\";\n/// Doc\nx # 1.5";
+        let tokenized = tokenize(source);
+        assert_eq!(tokenized.tokens, vec![
+            Token::Let,
+            Token::Ident("é"),
+            Token::Assign,
+            Token::String("a\\nb"),
+            Token::Semicolon,
+            Token::DocComment(" Doc"),
+            Token::Ident("x"),
+            Token::Illegal('#'),
+            Token::Float(1.5),
+        ]);
+        assert_eq!(tokenized.spans[3], Span::new(9, 6, 1, 9));
+        assert_eq!(tokenized.diagnostics.len(), 1);
+
+        // The text is borrowed from the source, not copied.
+        match tokenized.tokens[1] {
+            Token::Ident(ident) => assert_eq!(ident.as_ptr(), source[4..].as_ptr()),
+            other => panic!("{:?}", other),
+        }
+        match tokenized.tokens[3] {
+            Token::String(string) => assert_eq!(string.as_ptr(), source[10..].as_ptr()),
+            other => panic!("{:?}", other),
+        }
+
+        let tokenized = tokenize("\"open");
+        assert_eq!(tokenized.tokens, vec![Token::Illegal('"')]);
+    }
+
+    #[cfg(feature = "nom")]
+    #[test]
+    pub fn test_nom_input() {
+        use nom::branch::alt;
+        use nom::bytes::complete::{ tag, take };
+        use nom::combinator::eof;
+        use nom::multi::many0;
+        use nom::sequence::{ terminated, tuple };
+        use nom::IResult;
+
+        /// `let <identifier> = <integer or identifier>;`, giving the name and the value.
+        fn let_statement<'a>(input: Tokens<'a>) -> IResult<Tokens<'a>, (&'a str, Token<'a>)> {
+            let (input, (_, name, _, value, _)) = tuple((
+                tag(Tokens(&[Token::Let])),
+                tag(Tokens(&[Token::Ident("")])),
+                tag(Tokens(&[Token::Assign])),
+                alt((tag(Tokens(&[Token::Int(0)])), tag(Tokens(&[Token::Ident("")])))),
+                tag(Tokens(&[Token::Semicolon])),
+            ))(input)?;
+            match name.0 {
+                [Token::Ident(name)] => Ok((input, (name, value.0[0]))),
+                _ => unreachable!(),
+            }
+        }
+
+        let tokenized = tokenize("let x = 5; let y = x;");
+        let (rest, statements) = terminated(many0(let_statement), eof)(tokenized.tokens()).unwrap();
+        assert_eq!(rest, Tokens(&[]));
+        assert_eq!(statements, vec![("x", Token::Int(5)), ("y", Token::Ident("x"))]);
+
+        let tokenized = tokenize("let 5 = x;");
+        assert!(let_statement(tokenized.tokens()).is_err());
+
+        let tokenized = tokenize("let x");
+        let result: IResult<Tokens, Tokens> = take(2usize)(tokenized.tokens());
+        assert_eq!(result, Ok((Tokens(&[]), Tokens(&[Token::Let, Token::Ident("x")]))));
+        let result: IResult<Tokens, Tokens> = take(3usize)(tokenized.tokens());
+        assert!(result.is_err());
+    }
+}
@@ -1,4 +1,3 @@
-#![feature(new_zeroed)]
 #![allow(dead_code)]
 
 use std::convert::TryFrom;
@@ -8,7 +7,7 @@ use std::rc::Rc;
 use std::rc::Weak;
 use std::collections::HashMap;
 use std::cmp::PartialEq;
-use core_stable::token;
+use crate::token;
 use crate::operator::Operator;
 
 #[derive(Debug, Clone)]
@@ -16,8 +15,17 @@ pub struct Identifier<'a>(pub Weak<RefCell<Context<'a>>>, pub usize);
 #[derive(Debug, Clone, PartialEq)]
 pub enum Ident<'a> {
     Checked(Identifier<'a>),
+    /// One of `BUILTINS`, which no context defines.
+    Builtin(&'a str),
     UnChecked(&'a str)
 }
+
+/// The builtin functions of the evaluator, which the identifiers defined
+/// nowhere refer to.
+pub const BUILTINS: [&str; 18] = [
+    "len", "first", "last", "rest", "push", "puts", "type", "str", "int",
+    "float", "keys", "values", "contains", "range", "split", "join", "upper", "lower",
+];
 impl PartialEq for Identifier<'_> {
     fn eq(&self, other: &Self) -> bool {
         self.0.upgrade().unwrap() == other.0.upgrade().unwrap() && self.1 == other.1
@@ -33,7 +41,7 @@ impl<'a> Identifier<'a> {
             .borrow_mut()
             .idents
             .get(self.1)
-            .map(|&str|str)
+            .copied()
     }
 }
 
@@ -45,24 +53,22 @@ pub struct Context<'a> {
 }
 
 impl<'a> Context<'a> {
-    pub fn find_named_ident(this: Rc<RefCell<Context<'a>>>, identifier: &'a str) -> Option<Identifier<'a>> {
-        let ident = this
-            .borrow_mut()
+    /// The innermost definition of `identifier`, in this context or in the
+    /// enclosing ones, falling back to the builtin of that name.
+    pub fn find_named_ident(this: Rc<RefCell<Context<'a>>>, identifier: &'a str) -> Option<Ident<'a>> {
+        let index = this
+            .borrow()
             .idents
             .iter()
-            .enumerate()
-            .skip_while(|(_, &ident)| ident == identifier)
-            .last()
-            .map(|(index, &ident)| (index, ident));
-        Some(
-            ident
-            .map(|(index, _)| Identifier(Rc::downgrade(&this), index)))
-            .unwrap_or_else(move || 
-                this
-                .borrow_mut()
-                .parent
-                .as_ref()
-                .map(|parent| Self::find_named_ident(parent.upgrade().unwrap(), identifier)).unwrap_or(None))
+            .rposition(|&ident| ident == identifier);
+        if let Some(index) = index {
+            return Some(Ident::Checked(Identifier(Rc::downgrade(&this), index)));
+        }
+        let parent = this.borrow().parent.as_ref().and_then(Weak::upgrade);
+        match parent {
+            Some(parent) => Self::find_named_ident(parent, identifier),
+            None => BUILTINS.contains(&identifier).then(|| Ident::Builtin(identifier)),
+        }
     }
 
     pub fn register_named(this: Rc<RefCell<Context<'a>>>, identifier: &'a str) -> Identifier<'a> {
@@ -72,7 +78,7 @@ impl<'a> Context<'a> {
             .idents
             .iter()
             .enumerate()
-            .last()
+            .next_back()
             .map(|(index, _)| Identifier(Rc::downgrade(&this), index))
             .unwrap()
     }
@@ -186,11 +192,37 @@ impl<'a> TryFrom<token::Token<'a>> for Literal<'a> {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct LetStatement<'a> {
-    ident: Ident<'a>,
-    value: Box<Expression<'a>>,
+    pub ident: Ident<'a>,
+    pub value: Box<Expression<'a>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReturnStatement<'a> {
-    value: Box<Expression<'a>>,
+    pub value: Box<Expression<'a>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::{ Context, Ident, Identifier };
+
+    #[test]
+    fn test_find_named_ident() {
+        let global = Rc::new(RefCell::new(Context::default()));
+        let inner = Rc::new(RefCell::new(Context {
+            parent: Some(Rc::downgrade(&global)),
+            ..Context::default()
+        }));
+        Context::register_named(Rc::clone(&global), "x");
+        Context::register_named(Rc::clone(&global), "len");
+        Context::register_named(Rc::clone(&inner), "x");
+
+        let found = Context::find_named_ident(Rc::clone(&inner), "x");
+        assert_eq!(found, Some(Ident::Checked(Identifier(Rc::downgrade(&inner), 0))));
+        let found = Context::find_named_ident(Rc::clone(&inner), "len");
+        assert_eq!(found, Some(Ident::Checked(Identifier(Rc::downgrade(&global), 1))));
+        assert_eq!(Context::find_named_ident(Rc::clone(&inner), "puts"), Some(Ident::Builtin("puts")));
+        assert_eq!(Context::find_named_ident(inner, "nope"), None);
+    }
 }
\ No newline at end of file
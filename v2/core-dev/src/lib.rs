@@ -1,7 +1,8 @@
 extern crate core_stable;
 
-#[allow(unused_imports)]
-pub use core_stable::token;
+/// The tokens borrowing their text from the source, which the parsers take
+/// as input.
+pub use core_stable::tokens as token;
 
 
 
@@ -18,11 +19,9 @@ pub mod parser;
 #[allow(unused_imports)]
 pub mod operator;
 
-pub mod utils;
-
 #[macro_export] macro_rules! tokens {
     [$($tokens:ident$(($value:expr))?),*] => {
-        token::Tokens(&[$(token::Token::$tokens$(($value))?)*])
+        $crate::token::Tokens(&[$($crate::token::Token::$tokens$(($value))?),*])
     }
 }
 
@@ -1,11 +1,10 @@
-#![feature(impl_trait_in_bindings, if_let_guard)]
-
 use std::convert::{ TryFrom, TryInto };
-use core_stable::token;
+use crate::token;
 use crate::ast;
 
 macro_rules! op {
     { $left:ident = $right:ident($lhs:expr, $rhs:expr) } => {
+        #[allow(non_snake_case)]
         pub fn $left<'a>() -> (Operator<'a>, (u8, u8)) {
             (Operator::$right, ($lhs, $rhs))
         }
@@ -92,6 +91,9 @@ impl<'a> Operator<'a> {
             List,
             Map,
             If,
+            Member,
+            LParen,
+            RParen,
             Literal
         };
 
@@ -106,7 +108,10 @@ impl<'a> Operator<'a> {
             Pow => 2,
             Not => 1,
             Call | Index => 2,
+            Member => 2,
             List(n) | Map(n) => n,
+            If(has_else) => if has_else { 3 } else { 2 },
+            LParen | RParen => 0,
             Literal(_) => 1
         }
     }
@@ -118,7 +123,7 @@ pub fn binding_power<'a> (
     op: Option<token::Token<'a>>,
     prefix: bool,
 ) -> Option<(Operator<'a>, (u8, u8))> {
-    let op = op?.clone();
+    let op = op?;
     let res = match op {
         token::Token::LeftParen => L_GROUPING(),
         token::Token::RightParen => R_GROUPING(),
@@ -128,7 +133,7 @@ pub fn binding_power<'a> (
         token::Token::Plus | token::Token::Minus => PLUS(),
         token::Token::Asterisk | token::Token::Slash => MUL(),
         token::Token::Bang => NOT(),
-        token::Token::Point => MEMBER(),
+        token::Token::Dot => MEMBER(),
         literal if ast::Literal::try_from(literal).is_ok()  => (LITERAL.0(literal.try_into().unwrap()), LITERAL.1),
         _ => return None,
     };
@@ -137,30 +142,12 @@ pub fn binding_power<'a> (
 }
 
 impl<'a> crate::Parse<'a> for (Operator<'a>, (u8, u8)) {
-    fn parse(tokens: core_stable::token::Tokens<'a>) -> nom::IResult<core_stable::token::Tokens<'a>, Self> {
-        use nom::{
-            branch::alt,
-            bytes::complete::tag
-        };
-
-        let op = alt((
-            tag(crate::tokens![Plus]),
-            tag(crate::tokens![Assign]),
-            tag(crate::tokens![Comma]),
-            tag(crate::tokens![Eq]),
-            tag(crate::tokens![NotEq]),
-            tag(crate::tokens![GT]), tag(crate::tokens![LT]), tag(crate::tokens![GTE]), tag(crate::tokens![LTE]),
-            tag(crate::tokens![Plus], tag(crate::tokens![Minus]),
-            tag(crate::tokens![Mul]), tag(crate::tokens![Div]),
-
-            Pow,
-            Not,
-            Call, Index,
-            Member,
-            List(u16), Map(u16),
-            If(bool),
-            LParen, RParen,
-            Literal(ast::Literal<'a>)
-        ))(tokens)?;
+    /// The infix operator or the literal of the first token.
+    fn parse(tokens: token::Tokens<'a>) -> nom::IResult<token::Tokens<'a>, Self> {
+        let (rest, first) = nom::bytes::complete::take(1usize)(tokens)?;
+        match binding_power(first.0.first().copied(), false) {
+            Some(op) => Ok((rest, op)),
+            None => Err(nom::Err::Error(nom::error::Error::new(tokens, nom::error::ErrorKind::Tag))),
+        }
     }
 }